@@ -0,0 +1,247 @@
+use byteorder::ByteOrder;
+
+#[derive(Clone)]
+pub struct ChecksumInfo<E: ByteOrder> {
+    pub buffer: [u32; 16],
+    pub low: u32,
+    pub high: u32,
+    pub rom: [u8; 4096],
+    endianness: core::marker::PhantomData<E>
+}
+
+pub const fn checksum_function(a0: u32, a1: u32, a2: u32) -> u32 {
+    let a1 = if a1 == 0 { a2 } else { a1 };
+    
+    let prod = (a0 as u64) * (a1 as u64);
+    let hi = (prod >> 32) as u32;
+    let lo = prod as u32;
+    let diff = hi.wrapping_sub(lo);
+    if diff == 0 {
+        a0
+    } else {
+        diff
+    }
+}
+
+pub const MAGIC_NUMBER: u32 = 0x6c07_8965;
+
+/// The inputs of one round and the state it left behind.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Round {
+    pub loop_idx: u32,
+    pub data_last: u32,
+    pub data: u32,
+    pub data_next: u32,
+    pub buffer: [u32; 16],
+}
+
+impl<E: ByteOrder> ChecksumInfo<E> {
+    pub fn rom_word(&self, idx: usize) -> u32 {
+        E::read_u32(&self.rom[(idx * 4)..])
+    }
+
+    pub fn set_rom_word(&mut self, idx: usize, value: u32) {
+        E::write_u32(&mut self.rom[(idx * 4)..], value);
+    }
+
+    pub fn new(seed: u32, rom: [u8; 4096]) -> ChecksumInfo<E> {
+        let data = E::read_u32(&rom[0x40..]);
+        
+        ChecksumInfo {
+            buffer: initial_state(seed, data),
+            low: 0,
+            high: 0,
+            rom,
+            endianness: core::marker::PhantomData::<E>,
+        }
+    }
+    
+    pub fn calc_checksum(&mut self) {
+        self.checksum(0, 1008);
+    }
+    
+    pub fn checksum(&mut self, start: u32, count: u32) {
+        self.checksum_observed(start, count, |_| {});
+    }
+
+    /// Runs the same rounds as `checksum`, handing each one to `observe` as
+    /// it finishes.
+    pub fn checksum_observed<F: FnMut(&Round)>(&mut self, start: u32, count: u32, mut observe: F) {
+        let mut data_idx = (start as usize) * 4;
+        let mut loop_idx = start;
+        // when resuming, the first round still needs the word before `start`
+        // as its data_last; a fresh checksum uses the first word for both
+        let mut data = E::read_u32(&self.rom[(0x40+data_idx.saturating_sub(4))..]);
+        
+        loop {
+            loop_idx += 1;
+            let data_last = data;
+            data = E::read_u32(&self.rom[(0x40+data_idx)..]);
+            data_idx += 4;
+            let data_next = if loop_idx < 1008 { 
+                E::read_u32(&self.rom[(0x40+data_idx)..]) 
+            } else {
+                0
+            };
+            
+            round(&mut self.buffer, loop_idx, data_last, data, data_next);
+            
+            observe(&Round { loop_idx, data_last, data, data_next, buffer: self.buffer });
+            if loop_idx == 1008 || loop_idx == count { break; }
+        }
+    }
+    
+    pub fn finalize_checksum(&mut self) {
+        let (high, low) = finalize(&self.buffer);
+        self.high = high;
+        self.low = low;
+    }
+}
+
+/// The state before the first round, from the seed and the first word at 0x40.
+pub const fn initial_state(seed: u32, first_word: u32) -> [u32; 16] {
+    let init = MAGIC_NUMBER.wrapping_mul(seed & 0xFF).wrapping_add(1);
+    [init ^ first_word; 16]
+}
+
+/// Mixes one round's words into `buffer`. This and `finalize` are `const`, so
+/// a checksum can be worked out at compile time; `ChecksumInfo` runs them too.
+#[inline]
+pub const fn round(buffer: &mut [u32; 16], loop_idx: u32, data_last: u32, data: u32, data_next: u32) {
+    let sum = checksum_function(1007u32.wrapping_sub(loop_idx), data, loop_idx);
+    buffer[0] = buffer[0].wrapping_add(sum);
+    
+    let sum = checksum_function(buffer[1], data, loop_idx);
+    buffer[1] = sum;
+    buffer[2] ^= data;
+    
+    let sum = checksum_function(data.wrapping_add(5), MAGIC_NUMBER, loop_idx);
+    buffer[3] = buffer[3].wrapping_add(sum);
+    
+    if data_last < data {
+        let sum = checksum_function(buffer[9], data, loop_idx);
+        buffer[9] = sum;
+    }
+    else {
+        buffer[9] = buffer[9].wrapping_add(data);
+    }
+    
+    let shift = data_last & 0x1f;
+    let data_shifted_right = data >> shift;
+    let data_shifted_left = data.wrapping_shl(32 - shift);
+    let tmp = data_shifted_right | data_shifted_left;
+    buffer[4] = buffer[4].wrapping_add(tmp);
+    
+    let data_shifted_left = data << shift;
+    let data_shifted_right = data.wrapping_shr(32 - shift);
+    
+    let sum = checksum_function(buffer[7], data_shifted_left | data_shifted_right, loop_idx);
+    buffer[7] = sum;
+    
+    if data < buffer[6] {
+        buffer[6] = buffer[3].wrapping_add(buffer[6]) ^ data.wrapping_add(loop_idx);
+    }
+    else {
+        buffer[6] ^= buffer[4].wrapping_add(data);
+    }
+    
+    let shift = data_last >> 27;
+    let data_shifted_right = data.wrapping_shr(32 - shift);
+    let data_shifted_left = data << shift;
+    let tmp2 = data_shifted_right | data_shifted_left;
+    buffer[5] = buffer[5].wrapping_add(tmp2);
+    
+    let data_shifted_left = data.wrapping_shl(32 - shift);
+    let data_shifted_right = data >> shift;
+    
+    let sum = checksum_function(buffer[8], data_shifted_right | data_shifted_left, loop_idx);
+    buffer[8] = sum;
+    
+    if loop_idx == 1008 {
+        return;
+    }
+    
+    let sum = checksum_function(buffer[15], tmp2, loop_idx);
+    
+    let shift = data >> 27;
+    let data_shifted_left = data_next << shift;
+    let data_shifted_right = data_next.wrapping_shr(32 - shift);
+    
+    let sum = checksum_function(sum, data_shifted_left | data_shifted_right, loop_idx);
+    buffer[15] = sum;
+    
+    let sum = checksum_function(buffer[14], tmp, loop_idx);
+    
+    let shift = data & 0x1f;
+    let tmp2 = shift;
+    let data_shifted_left = data_next.wrapping_shl(32 - shift);
+    let data_shifted_right = data_next >> shift;
+    
+    let sum = checksum_function(sum, data_shifted_right | data_shifted_left, loop_idx);
+    buffer[14] = sum;
+    
+    let data_shifted_right = data >> tmp2;
+    let data_shifted_left = data.wrapping_shl(32 - tmp2);
+    let tmp3 = data_shifted_right | data_shifted_left;
+    
+    let shift = data_next & 0x1f;
+    let data_shifted_right = data_next >> shift;
+    let data_shifted_left = data_next.wrapping_shl(32 - shift);
+    
+    buffer[13] = buffer[13].wrapping_add(tmp3.wrapping_add(data_shifted_right | data_shifted_left));
+    
+    let sum = checksum_function(buffer[10].wrapping_add(data), data_next, loop_idx);
+    buffer[10] = sum;
+    
+    let sum = checksum_function(buffer[11] ^ data, data_next, loop_idx);
+    buffer[11] = sum;
+    
+    buffer[12] = buffer[12].wrapping_add(buffer[8] ^ data);
+}
+
+/// Folds the state left by the last round into (high, low).
+pub const fn finalize(buffer: &[u32; 16]) -> (u32, u32) {
+    let mut buf = [buffer[0]; 4];
+    
+    let mut i = 0;
+    while i < 16 {
+        let data = buffer[i];
+        
+        let shift = data & 0x1f;
+        let data_shifted_left = data.wrapping_shl(32 - shift);
+        let data_shifted_right = data >> shift;
+        let tmp = buf[0].wrapping_add(data_shifted_right | data_shifted_left);
+        buf[0] = tmp;
+        
+        if data < tmp {
+            buf[1] = buf[1].wrapping_add(data);
+        } else {
+            buf[1] = checksum_function(buf[1], data, i as u32);
+        }
+        
+        let tmp = (data & 0x02) >> 1;
+        let tmp2 = data & 0x01;
+        
+        if tmp == tmp2 {
+            buf[2] = buf[2].wrapping_add(data);
+        } else {
+            buf[2] = checksum_function(buf[2], data, i as u32);
+        }
+        
+        if tmp2 == 1 {
+            buf[3] ^= data;
+        } else {
+            buf[3] = checksum_function(buf[3], data, i as u32);
+        }
+        i += 1;
+    }
+    
+    let sum = checksum_function(buf[0], buf[1], 16);
+    let tmp = buf[3] ^ buf[2];
+    
+    let checksum = (sum as u64) << 32;
+    let checksum = checksum | (tmp as u64);
+    let checksum = checksum & 0xffffffffffffu64;
+    
+    ((checksum >> 32) as u32, checksum as u32)
+}
@@ -0,0 +1,101 @@
+//! The IPL3 checksum without the search around it. Nothing here needs `std`
+//! or an allocator, so it can run on a flashcart or loader; the `std` feature
+//! adds reading images through `std::io`.
+
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+use byteorder::BigEndian;
+
+pub mod checksum;
+pub mod source;
+
+use checksum::ChecksumInfo;
+
+/// The byte orders ROM dumps are found in, told apart by the first header word.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RomFormat {
+    /// Big-endian, as the cartridge bus sees it.
+    Z64,
+    /// Every 16-bit half swapped.
+    V64,
+    /// Every 32-bit word little-endian.
+    N64,
+}
+
+impl RomFormat {
+    pub fn detect(rom: &[u8]) -> Option<RomFormat> {
+        match rom.get(..4)? {
+            [0x80, 0x37, 0x12, 0x40] => Some(RomFormat::Z64),
+            [0x37, 0x80, 0x40, 0x12] => Some(RomFormat::V64),
+            [0x40, 0x12, 0x37, 0x80] => Some(RomFormat::N64),
+            _ => None,
+        }
+    }
+
+    /// Converts `rom` between this format and big-endian. Both swaps are their
+    /// own inverse, so this works in either direction.
+    pub fn swap(self, rom: &mut [u8]) {
+        match self {
+            RomFormat::Z64 => {}
+            RomFormat::V64 => rom.chunks_exact_mut(2).for_each(|half| half.swap(0, 1)),
+            RomFormat::N64 => rom.chunks_exact_mut(4).for_each(|word| word.reverse()),
+        }
+    }
+}
+
+pub const fn ipl3_checksum(seed: u32, rom: [u8; 4096]) -> (u32, u32) {
+    image_checksum(seed, &rom)
+}
+
+const fn read_word(rom: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([rom[at], rom[at + 1], rom[at + 2], rom[at + 3]])
+}
+
+/// The checksum of a big-endian image at least 4 KiB long, as (high, low).
+/// Being `const`, it can check an IPL3 embedded with `include_bytes!` at build
+/// time, here a blank one:
+///
+/// ```
+/// const BLANK: (u32, u32) = ipl3_core::image_checksum(0x3F, &[0; 4096]);
+/// const _: () = assert!(matches!(BLANK, (0x2982, 0xAD8C201D)));
+/// ```
+pub const fn image_checksum(seed: u32, rom: &[u8]) -> (u32, u32) {
+    let mut buffer = checksum::initial_state(seed, read_word(rom, 0x40));
+    let mut data = read_word(rom, 0x40);
+    let mut loop_idx = 1;
+    while loop_idx <= 1008 {
+        let data_last = data;
+        data = read_word(rom, 0x3C + loop_idx as usize * 4);
+        let data_next = if loop_idx < 1008 { read_word(rom, 0x40 + loop_idx as usize * 4) } else { 0 };
+        checksum::round(&mut buffer, loop_idx, data_last, data, data_next);
+        loop_idx += 1;
+    }
+    checksum::finalize(&buffer)
+}
+
+/// Recomputes the checksum of `rom` from scratch with the free words set to
+/// `y` and `x`, independently of any midstate used while searching.
+pub fn patched_checksum(seed: u32, rom: [u8; 4096], y: u32, x: u32) -> (u32, u32) {
+    let mut csum: ChecksumInfo<BigEndian> = ChecksumInfo::new(seed, rom);
+    csum.set_rom_word(1022, y);
+    csum.set_rom_word(1023, x);
+    csum.checksum(0, 1008);
+    csum.finalize_checksum();
+
+    (csum.high, csum.low)
+}
+
+/// Reads the first 4 KiB of an image, which hold the header and the IPL3, in
+/// big-endian whatever the byte order of the image.
+#[cfg(feature = "std")]
+pub fn read_ipl3<R: std::io::Read>(mut reader: R) -> std::io::Result<[u8; 4096]> {
+    let mut rom = [0; 4096];
+    reader.read_exact(&mut rom)?;
+    if let Some(format) = RomFormat::detect(&rom) {
+        format.swap(&mut rom);
+    }
+    Ok(rom)
+}
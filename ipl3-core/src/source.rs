@@ -0,0 +1,157 @@
+use byteorder::{BigEndian, ByteOrder};
+use core::fmt;
+
+use crate::checksum::{finalize, initial_state, round};
+use crate::RomFormat;
+
+/// Somewhere the checksum can read words from without the image being copied
+/// into a 4 KiB array first.
+pub trait WordSource {
+    type Error;
+
+    /// Reads the big-endian word at byte `offset`, which is always a multiple
+    /// of 4.
+    fn read_word(&mut self, offset: usize) -> Result<u32, Self::Error>;
+}
+
+impl<S: WordSource + ?Sized> WordSource for &mut S {
+    type Error = S::Error;
+
+    fn read_word(&mut self, offset: usize) -> Result<u32, S::Error> {
+        (**self).read_word(offset)
+    }
+}
+
+/// A word past the end of an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutOfBounds {
+    pub offset: usize,
+    /// The length of the image in bytes.
+    pub len: usize,
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the word at {:#X} is past the end of a {:#X} byte image", self.offset, self.len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OutOfBounds {}
+
+impl WordSource for &[u8] {
+    type Error = OutOfBounds;
+
+    fn read_word(&mut self, offset: usize) -> Result<u32, OutOfBounds> {
+        match self.get(offset..offset + 4) {
+            Some(word) => Ok(BigEndian::read_u32(word)),
+            None => Err(OutOfBounds { offset, len: self.len() }),
+        }
+    }
+}
+
+/// Reads an image stored in another byte order as if it were big-endian.
+pub struct Swapped<S> {
+    pub inner: S,
+    pub format: RomFormat,
+}
+
+impl<S: WordSource> Swapped<S> {
+    /// Works out the byte order from the first header word. Images without a
+    /// recognisable header are taken to be big-endian.
+    pub fn detect(mut inner: S) -> Result<Swapped<S>, S::Error> {
+        let magic = inner.read_word(0)?.to_be_bytes();
+        let format = RomFormat::detect(&magic).unwrap_or(RomFormat::Z64);
+        Ok(Swapped { inner, format })
+    }
+}
+
+impl<S: WordSource> WordSource for Swapped<S> {
+    type Error = S::Error;
+
+    fn read_word(&mut self, offset: usize) -> Result<u32, S::Error> {
+        let word = self.inner.read_word(offset)?;
+        Ok(match self.format {
+            RomFormat::Z64 => word,
+            RomFormat::V64 => ((word & 0x00FF_00FF) << 8) | ((word >> 8) & 0x00FF_00FF),
+            RomFormat::N64 => word.swap_bytes(),
+        })
+    }
+}
+
+/// Reads words from a stream, only seeking when they aren't read in order.
+#[cfg(feature = "std")]
+pub struct IoSource<R> {
+    inner: R,
+    position: Option<u64>,
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read + std::io::Seek> IoSource<R> {
+    pub fn new(inner: R) -> IoSource<R> {
+        IoSource { inner, position: None }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read + std::io::Seek> WordSource for IoSource<R> {
+    type Error = std::io::Error;
+
+    fn read_word(&mut self, offset: usize) -> std::io::Result<u32> {
+        let offset = offset as u64;
+        if self.position != Some(offset) {
+            self.position = None;
+            self.inner.seek(std::io::SeekFrom::Start(offset))?;
+        }
+        let mut word = [0; 4];
+        self.inner.read_exact(&mut word)?;
+        self.position = Some(offset + 4);
+        Ok(u32::from_be_bytes(word))
+    }
+}
+
+/// A file mapped into memory and read in place.
+#[cfg(feature = "mmap")]
+pub struct MappedFile(memmap2::Mmap);
+
+#[cfg(feature = "mmap")]
+impl MappedFile {
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<MappedFile> {
+        let file = std::fs::File::open(path)?;
+        // Safety: the map is only read, and nothing here writes to the file
+        // while it's mapped
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(MappedFile(map))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl WordSource for MappedFile {
+    type Error = OutOfBounds;
+
+    fn read_word(&mut self, offset: usize) -> Result<u32, OutOfBounds> {
+        (&self.0[..]).read_word(offset)
+    }
+}
+
+/// Runs the whole checksum over `source`, reading each word once and in
+/// order, and returns (high, low).
+pub fn source_checksum<S: WordSource>(seed: u32, mut source: S) -> Result<(u32, u32), S::Error> {
+    let first = source.read_word(0x40)?;
+    let mut buffer = initial_state(seed, first);
+
+    let mut data_last = first;
+    let mut data = first;
+    for loop_idx in 1..=1008 {
+        let data_next = if loop_idx < 1008 {
+            source.read_word(0x40 + loop_idx as usize * 4)?
+        } else {
+            0
+        };
+        round(&mut buffer, loop_idx, data_last, data, data_next);
+        data_last = data;
+        data = data_next;
+    }
+
+    Ok(finalize(&buffer))
+}
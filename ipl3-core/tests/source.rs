@@ -0,0 +1,76 @@
+use ipl3_core::source::{source_checksum, OutOfBounds, Swapped, WordSource};
+use ipl3_core::{image_checksum, RomFormat};
+
+/// Vector image 2 with a big-endian header magic, so its byte order can be
+/// detected.
+fn z64() -> Vec<u8> {
+    let mut rom = vec![0u8; 4096];
+    let mut state = 2u32;
+    for byte in rom.iter_mut() {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        *byte = (state >> 16) as u8;
+    }
+    rom[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+    rom
+}
+
+fn in_format(format: RomFormat) -> Vec<u8> {
+    let mut rom = z64();
+    format.swap(&mut rom);
+    rom
+}
+
+#[test]
+fn every_byte_order_matches_z64() {
+    let expected = image_checksum(0x3F, &z64());
+    assert_eq!(source_checksum(0x3F, &z64()[..]), Ok(expected));
+
+    for &format in &[RomFormat::Z64, RomFormat::V64, RomFormat::N64] {
+        let rom = in_format(format);
+        let swapped = Swapped::detect(&rom[..]).unwrap();
+        assert_eq!(swapped.format, format);
+        assert_eq!(source_checksum(0x3F, swapped), Ok(expected), "{:?}", format);
+    }
+}
+
+#[test]
+fn unrecognised_header_is_big_endian() {
+    let mut rom = z64();
+    rom[..4].copy_from_slice(&[0; 4]);
+    assert_eq!(Swapped::detect(&rom[..]).unwrap().format, RomFormat::Z64);
+}
+
+#[test]
+fn truncated_image_is_out_of_bounds() {
+    let rom = z64();
+    assert_eq!(source_checksum(0x3F, &rom[..2000]), Err(OutOfBounds { offset: 2000, len: 2000 }));
+    assert_eq!(source_checksum(0x3F, &rom[..4094]), Err(OutOfBounds { offset: 4092, len: 4094 }));
+
+    let v64 = in_format(RomFormat::V64);
+    let swapped = Swapped::detect(&v64[..3000]).unwrap();
+    assert_eq!(source_checksum(0x3F, swapped), Err(OutOfBounds { offset: 3000, len: 3000 }));
+    assert_eq!(Swapped::detect(&v64[..2]).err(), Some(OutOfBounds { offset: 0, len: 2 }));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn io_source_matches_the_slice() {
+    use ipl3_core::source::IoSource;
+    use std::io::{Cursor, ErrorKind};
+
+    let expected = image_checksum(0x3F, &z64());
+    for &format in &[RomFormat::Z64, RomFormat::V64, RomFormat::N64] {
+        let swapped = Swapped::detect(IoSource::new(Cursor::new(in_format(format)))).unwrap();
+        assert_eq!(source_checksum(0x3F, swapped).unwrap(), expected, "{:?}", format);
+    }
+
+    // reading out of order seeks back
+    let rom = z64();
+    let mut source = IoSource::new(Cursor::new(rom.clone()));
+    for &offset in &[0x40, 0x44, 0x10, 0xFFC, 0x14] {
+        assert_eq!(source.read_word(offset).unwrap(), (&rom[..]).read_word(offset).unwrap());
+    }
+
+    let truncated = source_checksum(0x3F, IoSource::new(Cursor::new(&rom[..2000])));
+    assert_eq!(truncated.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+}
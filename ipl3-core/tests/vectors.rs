@@ -0,0 +1,105 @@
+use byteorder::{BigEndian, ByteOrder};
+use ipl3_core::checksum::ChecksumInfo;
+use ipl3_core::{image_checksum, patched_checksum};
+
+struct Vector {
+    image: [u8; 4096],
+    seed: u32,
+    y: u32,
+    x: u32,
+    checksum: u64,
+}
+
+fn image(number: u32) -> [u8; 4096] {
+    let mut rom = [0u8; 4096];
+    let mut state = number;
+    for byte in rom.iter_mut() {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        *byte = (state >> 16) as u8;
+    }
+    rom
+}
+
+/// `image` at compile time, with the free words set.
+const fn const_image(number: u32, y: u32, x: u32) -> [u8; 4096] {
+    let mut rom = [0u8; 4096];
+    let mut state = number;
+    let mut i = 0;
+    while i < 4096 {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        rom[i] = (state >> 16) as u8;
+        i += 1;
+    }
+    let y = y.to_be_bytes();
+    let x = x.to_be_bytes();
+    let mut i = 0;
+    while i < 4 {
+        rom[4088 + i] = y[i];
+        rom[4092 + i] = x[i];
+        i += 1;
+    }
+    rom
+}
+
+fn number(field: &str) -> u64 {
+    match field.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).unwrap(),
+        None => field.parse().unwrap(),
+    }
+}
+
+fn vectors() -> Vec<Vector> {
+    include_str!("vectors.txt").lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            Vector {
+                image: image(number(fields[0]) as u32),
+                seed: number(fields[1]) as u32,
+                y: number(fields[2]) as u32,
+                x: number(fields[3]) as u32,
+                checksum: u64::from_str_radix(fields[4], 16).unwrap(),
+            }
+        })
+        .collect()
+}
+
+fn joined((high, low): (u32, u32)) -> u64 {
+    ((high as u64) << 32) | (low as u64)
+}
+
+#[test]
+fn checksums_match_the_vectors() {
+    for v in vectors() {
+        assert_eq!(joined(patched_checksum(v.seed, v.image, v.y, v.x)), v.checksum);
+
+        let mut rom = v.image;
+        BigEndian::write_u32(&mut rom[4088..], v.y);
+        BigEndian::write_u32(&mut rom[4092..], v.x);
+        assert_eq!(joined(image_checksum(v.seed, &rom)), v.checksum);
+    }
+}
+
+#[test]
+fn resuming_from_a_midstate_matches_the_vectors() {
+    for v in vectors() {
+        let mut csum: ChecksumInfo<BigEndian> = ChecksumInfo::new(v.seed, v.image);
+        csum.checksum(0, 1005);
+        csum.set_rom_word(1022, v.y);
+        csum.set_rom_word(1023, v.x);
+        csum.checksum(1005, 1008);
+        csum.finalize_checksum();
+        assert_eq!(joined((csum.high, csum.low)), v.checksum);
+    }
+}
+
+#[test]
+fn image_checksum_is_const() {
+    // vector 2, evaluated by the compiler
+    const CHECKSUM: (u32, u32) = image_checksum(0x3F, &const_image(2, 0x12345678, 0x9ABCDEF0));
+    const _: () = assert!(matches!(CHECKSUM, (0xF956, 0x663E8694)));
+
+    let v = &vectors()[1];
+    assert_eq!((v.seed, v.y, v.x), (0x3F, 0x12345678, 0x9ABCDEF0));
+    assert_eq!(joined(CHECKSUM), v.checksum);
+}
@@ -0,0 +1,13 @@
+// Generates the C header into OUT_DIR. include/ipl3.h is a checked-in copy
+// for tools that don't build this crate themselves; tests/c_api.rs fails
+// while it's stale.
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    cbindgen::generate(&crate_dir)
+        .expect("couldn't generate the C header")
+        .write_to_file(format!("{}/ipl3.h", out_dir));
+}
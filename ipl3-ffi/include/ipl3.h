@@ -0,0 +1,159 @@
+#ifndef IPL3_H
+#define IPL3_H
+
+/* Generated from src/lib.rs by cbindgen, don't edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum Ipl3Status {
+  IPL3_STATUS_OK,
+  /**
+   * The search range held no solution.
+   */
+  IPL3_STATUS_NOT_FOUND,
+  /**
+   * A pointer was null, a buffer too short or an index out of range.
+   */
+  IPL3_STATUS_BAD_INPUT,
+  /**
+   * The progress callback or the cancel flag stopped a search.
+   */
+  IPL3_STATUS_CANCELLED,
+  /**
+   * The search backend failed, such as a worker pool that couldn't start.
+   */
+  IPL3_STATUS_FAILED,
+} Ipl3Status;
+
+/**
+ * A checksum in progress, over the first 4 KiB of a ROM.
+ */
+typedef struct Ipl3Context Ipl3Context;
+
+/**
+ * Called as each tile of up to 4Mi candidates finishes, with how many have
+ * been tried and the y and x before which every candidate has been. Returning
+ * false stops the search.
+ */
+typedef bool (*Ipl3Progress)(void *user, uint64_t tried, uint32_t y, uint64_t x);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Starts a checksum of the `len` bytes at `rom`, which may be in any byte
+ * order, with the state before the first round. Returns NULL if `rom` is
+ * NULL or shorter than 4096 bytes. Free it with `ipl3_context_free`.
+ *
+ * # Safety
+ *
+ * `rom` must point to `len` readable bytes.
+ */
+struct Ipl3Context *ipl3_context_new(const uint8_t *rom, size_t len, uint32_t seed);
+
+/**
+ * Writes the 48-bit checksum of the `len` bytes at `rom`, which may be in
+ * any byte order, to `checksum`.
+ *
+ * # Safety
+ *
+ * `rom` must point to `len` readable bytes, and `checksum` must be writable.
+ */
+enum Ipl3Status ipl3_checksum(const uint8_t *rom, size_t len, uint32_t seed, uint64_t *checksum);
+
+/**
+ * Copies a context, such as a midstate to try several free words from.
+ *
+ * # Safety
+ *
+ * `ctx` must be NULL or come from this library and not have been freed.
+ */
+struct Ipl3Context *ipl3_context_clone(const struct Ipl3Context *ctx);
+
+/**
+ * # Safety
+ *
+ * `ctx` must be NULL or come from this library and not have been freed.
+ */
+void ipl3_context_free(struct Ipl3Context *ctx);
+
+/**
+ * Sets word `index` of the image, 1022 and 1023 being the free words y and
+ * x. Rounds that already read the word aren't rerun.
+ *
+ * # Safety
+ *
+ * `ctx` must be NULL or come from this library and not have been freed.
+ */
+enum Ipl3Status ipl3_context_set_word(struct Ipl3Context *ctx, size_t index, uint32_t value);
+
+/**
+ * Runs rounds `start + 1` to `count`, where `start` is the number of rounds
+ * already run. `ipl3_context_checksum(ctx, 0, 1005)` leaves the midstate
+ * before the free words, and `count` 1008 finishes the rounds.
+ *
+ * # Safety
+ *
+ * `ctx` must be NULL or come from this library and not have been freed.
+ */
+enum Ipl3Status ipl3_context_checksum(struct Ipl3Context *ctx, uint32_t start, uint32_t count);
+
+/**
+ * Copies the 16 state words to `state`.
+ *
+ * # Safety
+ *
+ * `ctx` must be NULL or come from this library and not have been freed, and
+ * `state` must be NULL or point to 16 writable words.
+ */
+enum Ipl3Status ipl3_context_state(const struct Ipl3Context *ctx, uint32_t *state);
+
+/**
+ * Folds the state after all 1008 rounds into the 48-bit checksum, or returns
+ * 0 for a NULL `ctx`.
+ *
+ * # Safety
+ *
+ * `ctx` must be NULL or come from this library and not have been freed.
+ */
+uint64_t ipl3_context_finalize(struct Ipl3Context *ctx);
+
+/**
+ * Searches y in `y_start..y_end` and x in `0..x_end` for free words giving
+ * one of the `target_count` checksums at `targets` on the bits of `mask`,
+ * starting from the image and seed of `ctx` whatever rounds it has run. On a
+ * match, writes the words to `y_out` and `x_out`.
+ *
+ * `progress` and `cancel` are checked between blocks of candidates. `cancel`
+ * may be NULL; otherwise the search stops once another thread stores a
+ * non-zero byte there.
+ *
+ * # Safety
+ *
+ * `ctx` must be NULL or come from this library and not have been freed,
+ * `targets` must point to `target_count` words, `cancel` must be NULL or
+ * valid for the whole search, and `y_out` and `x_out` must be writable.
+ */
+enum Ipl3Status ipl3_search(const struct Ipl3Context *ctx,
+                            const uint64_t *targets,
+                            size_t target_count,
+                            uint64_t mask,
+                            uint32_t y_start,
+                            uint64_t y_end,
+                            uint64_t x_end,
+                            Ipl3Progress progress,
+                            void *user,
+                            const uint8_t *cancel,
+                            uint32_t *y_out,
+                            uint32_t *x_out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* IPL3_H */
@@ -0,0 +1,244 @@
+//! A C API over the checksum and the CPU search. See include/ipl3.h, which is
+//! generated from this file.
+
+use byteorder::BigEndian;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use ipl3::checksum::ChecksumInfo;
+use ipl3::error::Error;
+use ipl3::report::{Progress, Reporter};
+use ipl3::rom::RomFormat;
+use ipl3::search::{cpu_search, CpuOptions, Outcome, SearchOptions};
+use ipl3::target::TargetSet;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ipl3Status {
+    Ok,
+    /// The search range held no solution.
+    NotFound,
+    /// A pointer was null, a buffer too short or an index out of range.
+    BadInput,
+    /// The progress callback or the cancel flag stopped a search.
+    Cancelled,
+    /// The search backend failed, such as a worker pool that couldn't start.
+    Failed,
+}
+
+/// A checksum in progress, over the first 4 KiB of a ROM.
+pub struct Ipl3Context {
+    csum: ChecksumInfo<BigEndian>,
+    seed: u32,
+}
+
+/// Called as each tile of up to 4Mi candidates finishes, with how many have
+/// been tried and the y and x before which every candidate has been. Returning
+/// false stops the search.
+pub type Ipl3Progress = Option<extern "C" fn(user: *mut c_void, tried: u64, y: u32, x: u64) -> bool>;
+
+/// Starts a checksum of the `len` bytes at `rom`, which may be in any byte
+/// order, with the state before the first round. Returns NULL if `rom` is
+/// NULL or shorter than 4096 bytes. Free it with `ipl3_context_free`.
+///
+/// # Safety
+///
+/// `rom` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_context_new(rom: *const u8, len: usize, seed: u32) -> *mut Ipl3Context {
+    if rom.is_null() || len < 4096 {
+        return std::ptr::null_mut();
+    }
+    let mut ipl3 = [0u8; 4096];
+    ipl3.copy_from_slice(std::slice::from_raw_parts(rom, 4096));
+    if let Some(format) = RomFormat::detect(&ipl3) {
+        format.swap(&mut ipl3);
+    }
+
+    Box::into_raw(Box::new(Ipl3Context {
+        csum: ChecksumInfo::new(seed, ipl3),
+        seed,
+    }))
+}
+
+/// Writes the 48-bit checksum of the `len` bytes at `rom`, which may be in
+/// any byte order, to `checksum`.
+///
+/// # Safety
+///
+/// `rom` must point to `len` readable bytes, and `checksum` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_checksum(rom: *const u8, len: usize, seed: u32, checksum: *mut u64) -> Ipl3Status {
+    let ctx = ipl3_context_new(rom, len, seed);
+    if ctx.is_null() || checksum.is_null() {
+        ipl3_context_free(ctx);
+        return Ipl3Status::BadInput;
+    }
+    ipl3_context_checksum(ctx, 0, 1008);
+    *checksum = ipl3_context_finalize(ctx);
+    ipl3_context_free(ctx);
+    Ipl3Status::Ok
+}
+
+/// Copies a context, such as a midstate to try several free words from.
+///
+/// # Safety
+///
+/// `ctx` must be NULL or come from this library and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_context_clone(ctx: *const Ipl3Context) -> *mut Ipl3Context {
+    match ctx.as_ref() {
+        Some(ctx) => Box::into_raw(Box::new(Ipl3Context { csum: ctx.csum.clone(), seed: ctx.seed })),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `ctx` must be NULL or come from this library and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_context_free(ctx: *mut Ipl3Context) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Sets word `index` of the image, 1022 and 1023 being the free words y and
+/// x. Rounds that already read the word aren't rerun.
+///
+/// # Safety
+///
+/// `ctx` must be NULL or come from this library and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_context_set_word(ctx: *mut Ipl3Context, index: usize, value: u32) -> Ipl3Status {
+    match ctx.as_mut() {
+        Some(ctx) if index < 1024 => {
+            ctx.csum.set_rom_word(index, value);
+            Ipl3Status::Ok
+        }
+        _ => Ipl3Status::BadInput,
+    }
+}
+
+/// Runs rounds `start + 1` to `count`, where `start` is the number of rounds
+/// already run. `ipl3_context_checksum(ctx, 0, 1005)` leaves the midstate
+/// before the free words, and `count` 1008 finishes the rounds.
+///
+/// # Safety
+///
+/// `ctx` must be NULL or come from this library and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_context_checksum(ctx: *mut Ipl3Context, start: u32, count: u32) -> Ipl3Status {
+    match ctx.as_mut() {
+        Some(ctx) if start < count && count <= 1008 => {
+            ctx.csum.checksum(start, count);
+            Ipl3Status::Ok
+        }
+        _ => Ipl3Status::BadInput,
+    }
+}
+
+/// Copies the 16 state words to `state`.
+///
+/// # Safety
+///
+/// `ctx` must be NULL or come from this library and not have been freed, and
+/// `state` must be NULL or point to 16 writable words.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_context_state(ctx: *const Ipl3Context, state: *mut u32) -> Ipl3Status {
+    match ctx.as_ref() {
+        Some(ctx) if !state.is_null() => {
+            std::slice::from_raw_parts_mut(state, 16).copy_from_slice(&ctx.csum.buffer);
+            Ipl3Status::Ok
+        }
+        _ => Ipl3Status::BadInput,
+    }
+}
+
+/// Folds the state after all 1008 rounds into the 48-bit checksum, or returns
+/// 0 for a NULL `ctx`.
+///
+/// # Safety
+///
+/// `ctx` must be NULL or come from this library and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_context_finalize(ctx: *mut Ipl3Context) -> u64 {
+    match ctx.as_mut() {
+        Some(ctx) => {
+            ctx.csum.finalize_checksum();
+            ((ctx.csum.high as u64) << 32) | (ctx.csum.low as u64)
+        }
+        None => 0,
+    }
+}
+
+/// Searches y in `y_start..y_end` and x in `0..x_end` for free words giving
+/// one of the `target_count` checksums at `targets` on the bits of `mask`,
+/// starting from the image and seed of `ctx` whatever rounds it has run. On a
+/// match, writes the words to `y_out` and `x_out`.
+///
+/// `progress` and `cancel` are checked between blocks of candidates. `cancel`
+/// may be NULL; otherwise the search stops once another thread stores a
+/// non-zero byte there.
+///
+/// # Safety
+///
+/// `ctx` must be NULL or come from this library and not have been freed,
+/// `targets` must point to `target_count` words, `cancel` must be NULL or
+/// valid for the whole search, and `y_out` and `x_out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ipl3_search(
+    ctx: *const Ipl3Context,
+    targets: *const u64,
+    target_count: usize,
+    mask: u64,
+    y_start: u32,
+    y_end: u64,
+    x_end: u64,
+    progress: Ipl3Progress,
+    user: *mut c_void,
+    cancel: *const u8,
+    y_out: *mut u32,
+    x_out: *mut u32,
+) -> Ipl3Status {
+    let ctx = match ctx.as_ref() {
+        Some(ctx) if !targets.is_null() && target_count > 0 && !y_out.is_null() && !x_out.is_null() => ctx,
+        _ => return Ipl3Status::BadInput,
+    };
+    let targets = TargetSet::from_checksums(std::slice::from_raw_parts(targets, target_count), mask);
+    let cancel = (cancel as *const AtomicU8).as_ref();
+    let cancelled = || matches!(cancel, Some(cancel) if cancel.load(Ordering::Relaxed) != 0);
+
+    let opts = SearchOptions {
+        seed: ctx.seed,
+        init: y_start,
+        y_end: y_end.min(1 << 32),
+        x_end: x_end.min(1 << 32),
+        ..SearchOptions::default()
+    };
+    if cancelled() {
+        opts.cancel.cancel();
+    }
+    let token = opts.cancel.clone();
+    let mut out = Reporter::sink().on_progress(|at: &Progress| {
+        let keep_going = match progress {
+            Some(progress) => progress(user, at.tried, at.y, at.x),
+            None => true,
+        };
+        if !keep_going || cancelled() {
+            token.cancel();
+        }
+    });
+
+    match cpu_search(ctx.csum.rom, &targets, &opts, &CpuOptions::default(), &mut out) {
+        Ok(Outcome::Found(hit)) => {
+            *y_out = hit.y;
+            *x_out = hit.x;
+            Ipl3Status::Ok
+        }
+        Ok(Outcome::Exhausted) | Ok(Outcome::BudgetExceeded) => Ipl3Status::NotFound,
+        Ok(Outcome::Cancelled) => Ipl3Status::Cancelled,
+        Err(Error::Backend(_)) => Ipl3Status::Failed,
+        Err(_) => Ipl3Status::BadInput,
+    }
+}
@@ -0,0 +1,155 @@
+/* Exercises the C API against the vectors the Rust core is tested with. Run
+ * with the path of ipl3-core/tests/vectors.txt; exits non-zero on a failure. */
+
+#include <stdint.h>
+#include <stdio.h>
+
+#include "ipl3.h"
+
+static int failures = 0;
+
+#define CHECK(cond) do { \
+    if (!(cond)) { \
+        fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+        failures++; \
+    } \
+} while (0)
+
+/* The image a vector names, see vectors.txt. */
+static void image(uint32_t number, uint8_t rom[4096]) {
+    uint32_t state = number;
+    for (int i = 0; i < 4096; i++) {
+        state = state * 1103515245u + 12345u;
+        rom[i] = (uint8_t)(state >> 16);
+    }
+}
+
+static void set_free_words(uint8_t rom[4096], uint32_t y, uint32_t x) {
+    for (int i = 0; i < 4; i++) {
+        rom[0xFF8 + i] = (uint8_t)(y >> (24 - 8 * i));
+        rom[0xFFC + i] = (uint8_t)(x >> (24 - 8 * i));
+    }
+}
+
+static void check_vector(uint32_t number, uint32_t seed, uint32_t y, uint32_t x, uint64_t expected) {
+    uint8_t rom[4096];
+    image(number, rom);
+
+    /* from a midstate, as a search would */
+    Ipl3Context *midstate = ipl3_context_new(rom, sizeof rom, seed);
+    CHECK(midstate != NULL);
+    CHECK(ipl3_context_checksum(midstate, 0, 1005) == IPL3_STATUS_OK);
+    Ipl3Context *ctx = ipl3_context_clone(midstate);
+    CHECK(ipl3_context_set_word(ctx, 1022, y) == IPL3_STATUS_OK);
+    CHECK(ipl3_context_set_word(ctx, 1023, x) == IPL3_STATUS_OK);
+    CHECK(ipl3_context_checksum(ctx, 1005, 1008) == IPL3_STATUS_OK);
+    CHECK(ipl3_context_finalize(ctx) == expected);
+    ipl3_context_free(ctx);
+    ipl3_context_free(midstate);
+
+    /* and in one go */
+    uint64_t checksum = 0;
+    set_free_words(rom, y, x);
+    CHECK(ipl3_checksum(rom, sizeof rom, seed, &checksum) == IPL3_STATUS_OK);
+    CHECK(checksum == expected);
+}
+
+struct calls {
+    uint32_t count;
+    uint64_t tried;
+    uint32_t y;
+    uint64_t x;
+};
+
+static bool stop_after_first(void *user, uint64_t tried, uint32_t y, uint64_t x) {
+    struct calls *calls = user;
+    calls->count++;
+    calls->tried = tried;
+    calls->y = y;
+    calls->x = x;
+    return false;
+}
+
+static void check_search(void) {
+    uint8_t rom[4096];
+    image(1, rom);
+    Ipl3Context *ctx = ipl3_context_new(rom, sizeof rom, 0x3F);
+
+    /* 12 bits is found within the first 64Ki x nearly always */
+    const uint64_t target = 0x68D3E7809B83;
+    const uint64_t mask = 0xFFF;
+    uint32_t y = 0, x = 0;
+    CHECK(ipl3_search(ctx, &target, 1, mask, 0, 1, 1 << 16, NULL, NULL, NULL, &y, &x) == IPL3_STATUS_OK);
+    uint64_t checksum = 0;
+    set_free_words(rom, y, x);
+    CHECK(ipl3_checksum(rom, sizeof rom, 0x3F, &checksum) == IPL3_STATUS_OK);
+    CHECK((checksum & mask) == (target & mask));
+
+    /* an arbitrary 48-bit checksum won't be hit in a few candidates */
+    const uint64_t unlikely = 0x123456789ABC;
+    CHECK(ipl3_search(ctx, &unlikely, 1, 0xFFFFFFFFFFFF, 0, 2, 16, NULL, NULL, NULL, &y, &x) == IPL3_STATUS_NOT_FOUND);
+
+    /* enough y that the workers can't finish them all before they notice */
+    struct calls calls = {0};
+    CHECK(ipl3_search(ctx, &unlikely, 1, 0xFFFFFFFFFFFF, 0, 1 << 20, 16, stop_after_first, &calls, NULL, &y, &x)
+          == IPL3_STATUS_CANCELLED);
+    CHECK(calls.count >= 1);
+    CHECK(calls.tried % 16 == 0 && calls.tried <= 16 * calls.count);
+
+    uint8_t cancel = 1;
+    CHECK(ipl3_search(ctx, &unlikely, 1, 0xFFFFFFFFFFFF, 0, 4, 16, NULL, NULL, &cancel, &y, &x)
+          == IPL3_STATUS_CANCELLED);
+
+    ipl3_context_free(ctx);
+}
+
+static void check_bad_input(void) {
+    uint8_t rom[4096] = {0};
+    uint64_t checksum;
+    CHECK(ipl3_context_new(NULL, 4096, 0x3F) == NULL);
+    CHECK(ipl3_context_new(rom, 100, 0x3F) == NULL);
+    CHECK(ipl3_checksum(rom, 100, 0x3F, &checksum) == IPL3_STATUS_BAD_INPUT);
+
+    Ipl3Context *ctx = ipl3_context_new(rom, sizeof rom, 0x3F);
+    CHECK(ipl3_context_set_word(ctx, 1024, 0) == IPL3_STATUS_BAD_INPUT);
+    CHECK(ipl3_context_checksum(ctx, 0, 1009) == IPL3_STATUS_BAD_INPUT);
+    CHECK(ipl3_context_set_word(NULL, 0, 0) == IPL3_STATUS_BAD_INPUT);
+    ipl3_context_free(ctx);
+    ipl3_context_free(NULL);
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s VECTORS\n", argv[0]);
+        return 2;
+    }
+    FILE *vectors = fopen(argv[1], "r");
+    if (vectors == NULL) {
+        perror(argv[1]);
+        return 2;
+    }
+
+    char line[256];
+    int checked = 0;
+    while (fgets(line, sizeof line, vectors) != NULL) {
+        unsigned number, seed, y, x;
+        unsigned long long checksum;
+        if (line[0] == '#' || sscanf(line, "%u %x %x %x %llx", &number, &seed, &y, &x, &checksum) != 5) {
+            continue;
+        }
+        check_vector(number, seed, y, x, checksum);
+        checked++;
+    }
+    fclose(vectors);
+    CHECK(checked > 0);
+
+    check_search();
+    check_bad_input();
+
+    if (failures != 0) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    printf("%d vectors ok\n", checked);
+    return 0;
+}
@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+use std::path::Path;
+use std::process::Command;
+
+// The checked-in header must match the one the build generates. Run with
+// IPL3_UPDATE_HEADER set to copy it over.
+#[test]
+fn header_is_up_to_date() {
+    let generated = std::fs::read_to_string(Path::new(env!("OUT_DIR")).join("ipl3.h")).unwrap();
+    let checked_in = Path::new(env!("CARGO_MANIFEST_DIR")).join("include/ipl3.h");
+    if std::env::var_os("IPL3_UPDATE_HEADER").is_some() {
+        std::fs::write(&checked_in, &generated).unwrap();
+    }
+    assert!(
+        std::fs::read_to_string(&checked_in).unwrap() == generated,
+        "include/ipl3.h is stale, rerun with IPL3_UPDATE_HEADER=1 to regenerate it"
+    );
+}
+
+// Builds tests/c_api.c against the generated header and the staticlib, then
+// runs it over the shared vectors.
+#[test]
+fn c_program_passes() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // cargo test leaves the staticlib in target/<profile>/deps beside this
+    // test, cargo build one level up
+    let deps = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let lib = [deps.clone(), deps.parent().unwrap().to_path_buf()].iter()
+        .map(|dir| dir.join("libipl3_ffi.a"))
+        .find(|lib| lib.exists())
+        .expect("no libipl3_ffi.a next to the test");
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("c_api");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(manifest.join("tests/c_api.c"))
+        .arg("-I")
+        .arg(env!("OUT_DIR"))
+        .arg(&lib)
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&program)
+        .status()
+        .unwrap();
+    assert!(status.success(), "couldn't build the C test program");
+
+    let status = Command::new(&program)
+        .arg(manifest.join("../ipl3-core/tests/vectors.txt"))
+        .status()
+        .unwrap();
+    assert!(status.success(), "the C test program failed");
+}
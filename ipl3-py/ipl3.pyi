@@ -0,0 +1,30 @@
+from typing import Callable, List, Optional, Sequence, Tuple, Union
+
+__version__: str
+FULL_MASK: int
+
+Rom = Union[bytes, bytearray]
+
+def checksum(rom: Rom, seed: int = 0x3F) -> int: ...
+def cics() -> List[Tuple[str, int, int]]: ...
+def detect_cic(rom: Rom) -> List[str]: ...
+
+class Midstate:
+    def __init__(self, rom: Rom, seed: int = 0x3F) -> None: ...
+    @property
+    def state(self) -> List[int]: ...
+    def checksum(self, y: int, x: int) -> int: ...
+
+def search(
+    rom: Rom,
+    targets: Union[int, Sequence[int]],
+    seed: int = 0x3F,
+    mask: int = FULL_MASK,
+    y_start: int = 0,
+    y_end: int = 1 << 32,
+    x_end: int = 1 << 32,
+    progress: Optional[Callable[[int, int, int], Optional[bool]]] = None,
+    max_time: Optional[float] = None,
+    max_y: Optional[int] = None,
+    max_candidates: Optional[int] = None,
+) -> Optional[Tuple[int, int]]: ...
@@ -0,0 +1,190 @@
+use byteorder::BigEndian;
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes};
+use std::time::Duration;
+
+use ipl3::checksum::ChecksumInfo;
+use ipl3::cic;
+use ipl3::error::Error;
+use ipl3::report::{Progress, Reporter};
+use ipl3::rom::{ipl3_checksum, RomFormat};
+use ipl3::search::{cpu_search, midstate, y_midstate, Budget, CpuOptions, Outcome, SearchOptions};
+use ipl3::target::{TargetSet, FULL_MASK};
+
+fn to_py(e: Error) -> PyErr {
+    match e {
+        Error::Io { .. } | Error::Output(_) => PyOSError::new_err(e.to_string()),
+        Error::Backend(_) => PyRuntimeError::new_err(e.to_string()),
+        _ => PyValueError::new_err(e.to_string()),
+    }
+}
+
+/// Copies the header and IPL3 out of `rom`, a bytes or bytearray in any byte
+/// order, as big-endian.
+fn ipl3_of(rom: &Bound<PyAny>) -> PyResult<[u8; 4096]> {
+    let mut ipl3 = match rom.downcast::<PyByteArray>() {
+        // copied, as Python code may resize it while it's borrowed
+        Ok(array) => ipl3::rom::ipl3_of("the ROM", &array.to_vec()),
+        Err(_) => ipl3::rom::ipl3_of("the ROM", rom.downcast::<PyBytes>()?.as_bytes()),
+    }.map_err(to_py)?;
+    if let Some(format) = RomFormat::detect(&ipl3) {
+        format.swap(&mut ipl3);
+    }
+    Ok(ipl3)
+}
+
+fn joined((high, low): (u32, u32)) -> u64 {
+    ((high as u64) << 32) | (low as u64)
+}
+
+/// The 48-bit IPL3 checksum of a ROM in any byte order.
+#[pyfunction]
+#[pyo3(signature = (rom, seed = 0x3F))]
+fn checksum(rom: &Bound<PyAny>, seed: u32) -> PyResult<u64> {
+    Ok(joined(ipl3_checksum(seed, ipl3_of(rom)?)))
+}
+
+/// Every known CIC as (name, seed, retail checksum).
+#[pyfunction]
+fn cics() -> Vec<(&'static str, u32, u64)> {
+    cic::CICS.iter().map(|cic| (cic.name, cic.seed, cic.checksum)).collect()
+}
+
+/// The names of the CICs whose retail checksum a ROM reproduces.
+#[pyfunction]
+fn detect_cic(rom: &Bound<PyAny>) -> PyResult<Vec<&'static str>> {
+    Ok(cic::detect(ipl3_of(rom)?).iter().map(|cic| cic.name).collect())
+}
+
+/// The checksum state of a ROM after every round that doesn't depend on the
+/// free words, so candidates for them can be tried cheaply.
+#[pyclass]
+struct Midstate {
+    csum: ChecksumInfo<BigEndian>,
+}
+
+#[pymethods]
+impl Midstate {
+    #[new]
+    #[pyo3(signature = (rom, seed = 0x3F))]
+    fn new(rom: &Bound<PyAny>, seed: u32) -> PyResult<Midstate> {
+        Ok(Midstate { csum: midstate(seed, ipl3_of(rom)?) })
+    }
+
+    /// The 16 state words.
+    #[getter]
+    fn state(&self) -> [u32; 16] {
+        self.csum.buffer
+    }
+
+    /// The 48-bit checksum with the free words set to `y` and `x`.
+    fn checksum(&self, y: u32, x: u32) -> u64 {
+        let mut csum = y_midstate(&self.csum, y);
+        csum.set_rom_word(1023, x);
+        csum.checksum(1006, 1008);
+        csum.finalize_checksum();
+        joined((csum.high, csum.low))
+    }
+}
+
+/// Searches the CPU for free words giving one of `targets`, a checksum or a
+/// non-empty list of them, on the bits of `mask`. Returns (y, x), or None once
+/// the range is exhausted, a budget runs out or the search is stopped.
+///
+/// `progress` is called with (candidates tried, y, x) as each tile of up to
+/// 4Mi candidates finishes, every candidate before (y, x) having been tried;
+/// returning False stops the search, as does raising or Ctrl-C. The GIL is
+/// released in between.
+#[pyfunction]
+#[pyo3(signature = (
+    rom, targets, seed = 0x3F, mask = FULL_MASK, y_start = 0, y_end = 1 << 32, x_end = 1 << 32, progress = None,
+    max_time = None, max_y = None, max_candidates = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn search(
+    py: Python,
+    rom: &Bound<PyAny>,
+    targets: &Bound<PyAny>,
+    seed: u32,
+    mask: u64,
+    y_start: u32,
+    y_end: u64,
+    x_end: u64,
+    progress: Option<PyObject>,
+    max_time: Option<f64>,
+    max_y: Option<u64>,
+    max_candidates: Option<u64>,
+) -> PyResult<Option<(u32, u32)>> {
+    let source = ipl3_of(rom)?;
+    let checksums: Vec<u64> = match targets.extract::<u64>() {
+        Ok(checksum) => vec![checksum],
+        Err(_) => targets.extract()?,
+    };
+    if checksums.is_empty() {
+        return Err(PyValueError::new_err("no target checksums given"));
+    }
+    let targets = TargetSet::from_checksums(&checksums, mask);
+
+    let duration = match max_time {
+        Some(seconds) => Some(Duration::try_from_secs_f64(seconds)
+            .map_err(|e| PyValueError::new_err(format!("bad max_time {}: {}", seconds, e)))?),
+        None => None,
+    };
+    let opts = SearchOptions {
+        seed,
+        init: y_start,
+        y_end: y_end.min(1 << 32),
+        x_end: x_end.min(1 << 32),
+        budget: Budget {
+            duration,
+            y_count: max_y,
+            candidates: max_candidates,
+        },
+        ..SearchOptions::default()
+    };
+
+    // an exception from the callback or a signal handler stops the search
+    // and is raised once it has
+    let mut raised = None;
+    let cancel = opts.cancel.clone();
+    let outcome = py.allow_threads(|| {
+        let mut out = Reporter::sink().on_progress(|at: &Progress| {
+            Python::with_gil(|py| {
+                let keep_going = py.check_signals().and_then(|()| match &progress {
+                    Some(progress) => Ok(!matches!(progress.call1(py, (at.tried, at.y, at.x))?.extract::<bool>(py), Ok(false))),
+                    None => Ok(true),
+                });
+                match keep_going {
+                    Ok(true) => {}
+                    Ok(false) => cancel.cancel(),
+                    Err(e) => {
+                        raised = Some(e);
+                        cancel.cancel();
+                    }
+                }
+            })
+        });
+        cpu_search(source, &targets, &opts, &CpuOptions::default(), &mut out)
+    }).map_err(to_py)?;
+    if let Some(e) = raised {
+        return Err(e);
+    }
+    Ok(match outcome {
+        Outcome::Found(hit) => Some((hit.y, hit.x)),
+        _ => None,
+    })
+}
+
+#[pymodule]
+#[pyo3(name = "ipl3")]
+fn ipl3_py(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("FULL_MASK", FULL_MASK)?;
+    m.add_function(wrap_pyfunction!(checksum, m)?)?;
+    m.add_function(wrap_pyfunction!(cics, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_cic, m)?)?;
+    m.add_function(wrap_pyfunction!(search, m)?)?;
+    m.add_class::<Midstate>()?;
+    Ok(())
+}
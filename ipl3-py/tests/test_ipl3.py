@@ -0,0 +1,119 @@
+import pathlib
+import struct
+import unittest
+
+import ipl3
+
+# the same vectors the Rust core is tested against
+VECTORS = pathlib.Path(__file__).resolve().parents[2] / "ipl3-core" / "tests" / "vectors.txt"
+
+
+def image(number):
+    """The image a vector names, see vectors.txt."""
+    state = number
+    rom = bytearray(4096)
+    for i in range(len(rom)):
+        state = (state * 1103515245 + 12345) & 0xFFFFFFFF
+        rom[i] = (state >> 16) & 0xFF
+    return rom
+
+
+def with_free_words(rom, y, x):
+    rom = bytearray(rom)
+    rom[0xFF8:0x1000] = struct.pack(">II", y, x)
+    return bytes(rom)
+
+
+def vectors():
+    for line in VECTORS.read_text().splitlines():
+        if not line or line.startswith("#"):
+            continue
+        number, seed, y, x, checksum = line.split()
+        yield image(int(number)), int(seed, 16), int(y, 16), int(x, 16), int(checksum, 16)
+
+
+def swapped(rom, size):
+    """Reverses every `size` byte group, turning a z64 image into a v64 (2) or
+    n64 (4) one."""
+    return b"".join(rom[i:i + size][::-1] for i in range(0, len(rom), size))
+
+
+class ChecksumTest(unittest.TestCase):
+    def test_vectors(self):
+        for rom, seed, y, x, expected in vectors():
+            self.assertEqual(ipl3.checksum(with_free_words(rom, y, x), seed), expected)
+
+    def test_byte_orders(self):
+        rom, seed, y, x, expected = next(vectors())
+        # the header isn't checksummed, so the magic can go in
+        rom = b"\x80\x37\x12\x40" + with_free_words(rom, y, x)[4:]
+        for size in (2, 4):
+            self.assertEqual(ipl3.checksum(swapped(rom, size), seed), expected)
+
+    def test_short_rom(self):
+        with self.assertRaises(ValueError):
+            ipl3.checksum(bytes(100))
+
+    def test_midstate(self):
+        for rom, seed, y, x, expected in vectors():
+            self.assertEqual(ipl3.Midstate(rom, seed).checksum(y, x), expected)
+
+
+class CicTest(unittest.TestCase):
+    def test_table(self):
+        self.assertIn(("6102", 0x3F, 0xA536C0F1D859), ipl3.cics())
+
+    def test_unknown_ipl3(self):
+        self.assertEqual(ipl3.detect_cic(image(1)), [])
+
+
+class SearchTest(unittest.TestCase):
+    def test_masked_target(self):
+        rom, seed, _, _, target = next(vectors())
+        mask = 0xFFF
+        hit = ipl3.search(rom, target, seed, mask=mask, y_end=1, x_end=1 << 16)
+        self.assertIsNotNone(hit)
+        y, x = hit
+        self.assertEqual(ipl3.checksum(with_free_words(rom, y, x), seed) & mask, target & mask)
+
+    def test_cancel(self):
+        seen = []
+
+        def progress(tried, y, x):
+            seen.append((tried, y, x))
+            return False
+
+        rom, seed, _, _, _ = next(vectors())
+        self.assertIsNone(ipl3.search(rom, [0, 1], seed, x_end=16, progress=progress))
+        # workers may finish more tiles before they notice
+        self.assertEqual(seen[0][0], 16)
+
+    def test_raising_progress(self):
+        def progress(tried, y, x):
+            raise KeyError(y)
+
+        rom, seed, _, _, _ = next(vectors())
+        with self.assertRaises(KeyError):
+            ipl3.search(rom, [0, 1], seed, x_end=16, progress=progress)
+
+    def test_budget(self):
+        seen = []
+        rom, seed, _, _, _ = next(vectors())
+        self.assertIsNone(ipl3.search(rom, [0, 1], seed, x_end=16, max_y=3,
+                                      progress=lambda tried, y, x: seen.append((tried, y, x))))
+        self.assertEqual(seen[-1], (48, 3, 0))
+
+    def test_bad_max_time(self):
+        rom, seed, _, _, _ = next(vectors())
+        for max_time in [-1.0, float("nan"), float("inf")]:
+            with self.assertRaises(ValueError):
+                ipl3.search(rom, [0, 1], seed, x_end=16, max_time=max_time)
+
+    def test_no_targets(self):
+        rom, seed, _, _, _ = next(vectors())
+        with self.assertRaises(ValueError):
+            ipl3.search(rom, [], seed, x_end=16)
+
+
+if __name__ == "__main__":
+    unittest.main()
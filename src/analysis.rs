@@ -0,0 +1,181 @@
+use byteorder::BigEndian;
+use rand::Rng;
+use std::io::prelude::*;
+
+use crate::checksum::ChecksumInfo;
+use crate::target::TargetSet;
+
+// y occupies the first 32 input bits and x the second, both MSB first
+const INPUT_BITS: usize = 64;
+const OUTPUT_BITS: usize = 48;
+
+/// Runs the last three rounds and finalization from `midstate`, reusing `work`
+/// so the 4 KiB image isn't copied for every sample.
+fn final_rounds(work: &mut ChecksumInfo<BigEndian>, midstate: &[u32; 16], y: u32, x: u32) -> u64 {
+    work.buffer = *midstate;
+    work.set_rom_word(1022, y);
+    work.set_rom_word(1023, x);
+    work.checksum(1005, 1008);
+    work.finalize_checksum();
+
+    ((work.high as u64) << 32) | (work.low as u64)
+}
+
+fn flip_input(y: u32, x: u32, bit: usize) -> (u32, u32) {
+    if bit < 32 {
+        (y ^ (1 << (31 - bit)), x)
+    } else {
+        (y, x ^ (1 << (63 - bit)))
+    }
+}
+
+fn input_name(bit: usize) -> String {
+    if bit < 32 {
+        format!("y{:02}", 31 - bit)
+    } else {
+        format!("x{:02}", 63 - bit)
+    }
+}
+
+pub struct Analysis {
+    pub samples: u64,
+    /// How often each output bit (47 down to 0) was set.
+    pub ones: [u64; OUTPUT_BITS],
+    /// How often flipping an input bit flipped each output bit.
+    pub avalanche: Vec<[u64; OUTPUT_BITS]>,
+    /// The worst absolute bit bias seen within each sampled y.
+    pub per_y: Vec<(u32, f64)>,
+}
+
+impl Analysis {
+    fn bit_probability(&self, out_bit: usize) -> f64 {
+        self.ones[out_bit] as f64 / self.samples as f64
+    }
+
+    /// Estimates the candidates needed to hit `target` on the bits of `mask`,
+    /// treating the output bits as independent with their measured bias.
+    pub fn estimated_work(&self, high: u32, low: u32, mask: u64) -> f64 {
+        let target = ((high as u64) << 32) | (low as u64);
+        let mut log2_p = 0.0;
+        for out_bit in 0..OUTPUT_BITS {
+            let shift = OUTPUT_BITS - 1 - out_bit;
+            if (mask >> shift) & 1 == 0 {
+                continue;
+            }
+
+            let p_one = self.bit_probability(out_bit);
+            let p = if (target >> shift) & 1 == 1 { p_one } else { 1.0 - p_one };
+            // a bit that never took the needed value would make the target
+            // unreachable; clamp to one sample's worth instead of dividing by 0
+            log2_p += p.max(0.5 / self.samples as f64).log2();
+        }
+
+        -log2_p
+    }
+}
+
+/// Samples the final-round function over random free words, starting from a
+/// midstate after 1005 rounds.
+pub fn analyze(pre_csum: &ChecksumInfo<BigEndian>, samples: u64, y_count: u64) -> Analysis {
+    let mut rng = rand::thread_rng();
+    let mut work = pre_csum.clone();
+    let midstate = pre_csum.buffer;
+
+    let mut analysis = Analysis {
+        samples: 0,
+        ones: [0; OUTPUT_BITS],
+        avalanche: vec![[0; OUTPUT_BITS]; INPUT_BITS],
+        per_y: Vec::new(),
+    };
+
+    let per_y_samples = (samples / y_count.max(1)).max(1);
+    for _ in 0..y_count.max(1) {
+        let y: u32 = rng.gen();
+        let mut y_ones = [0u64; OUTPUT_BITS];
+
+        for _ in 0..per_y_samples {
+            let x: u32 = rng.gen();
+            let out = final_rounds(&mut work, &midstate, y, x);
+            for (out_bit, ones) in y_ones.iter_mut().enumerate() {
+                if (out >> (OUTPUT_BITS - 1 - out_bit)) & 1 == 1 {
+                    *ones += 1;
+                }
+            }
+
+            for in_bit in 0..INPUT_BITS {
+                let (fy, fx) = flip_input(y, x, in_bit);
+                let diff = out ^ final_rounds(&mut work, &midstate, fy, fx);
+                for (out_bit, flips) in analysis.avalanche[in_bit].iter_mut().enumerate() {
+                    if (diff >> (OUTPUT_BITS - 1 - out_bit)) & 1 == 1 {
+                        *flips += 1;
+                    }
+                }
+            }
+        }
+
+        let worst = y_ones.iter()
+            .map(|&ones| (ones as f64 / per_y_samples as f64 - 0.5).abs())
+            .fold(0.0, f64::max);
+        analysis.per_y.push((y, worst));
+
+        for (total, ones) in analysis.ones.iter_mut().zip(y_ones.iter()) {
+            *total += ones;
+        }
+        analysis.samples += per_y_samples;
+    }
+
+    analysis
+}
+
+pub fn print_report<W: Write>(out: &mut W, analysis: &Analysis, targets: &TargetSet) -> std::io::Result<()> {
+    let n = analysis.samples as f64;
+    let sd = 0.5 / n.sqrt();
+
+    writeln!(out, "Sampled {} outputs over {} y values", analysis.samples, analysis.per_y.len())?;
+    writeln!(out, "Output bit bias (P(1) - 0.5), one standard deviation is {:.5}:", sd)?;
+    let mut worst = (0, 0.0f64);
+    for out_bit in 0..OUTPUT_BITS {
+        let bias = analysis.bit_probability(out_bit) - 0.5;
+        if bias.abs() > worst.1.abs() {
+            worst = (out_bit, bias);
+        }
+        writeln!(out, "  bit {:2}: {:+.5}", OUTPUT_BITS - 1 - out_bit, bias)?;
+    }
+    writeln!(out, "Worst bit: {} at {:+.5} ({:.1} sd)",
+             OUTPUT_BITS - 1 - worst.0, worst.1, worst.1.abs() / sd)?;
+
+    writeln!(out, "Avalanche matrix, flip probability rounded to tenths (columns are output bits 47..0):")?;
+    let mut worst_cell = (0, 0, 0.5f64);
+    for in_bit in 0..INPUT_BITS {
+        let row: String = analysis.avalanche[in_bit].iter().enumerate().map(|(out_bit, &flips)| {
+            let p = flips as f64 / n;
+            if (p - 0.5).abs() > (worst_cell.2 - 0.5).abs() {
+                worst_cell = (in_bit, out_bit, p);
+            }
+            std::char::from_digit(((p * 10.0).round() as u32).min(9), 10).unwrap()
+        }).collect();
+        writeln!(out, "  {} {}", input_name(in_bit), row)?;
+    }
+    writeln!(out, "Weakest avalanche: {} -> bit {} flips with probability {:.4}",
+             input_name(worst_cell.0), OUTPUT_BITS - 1 - worst_cell.1, worst_cell.2)?;
+
+    if analysis.per_y.len() > 1 {
+        let mut per_y = analysis.per_y.clone();
+        per_y.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let per_y_sd = 0.5 / (n / per_y.len() as f64).sqrt();
+        writeln!(out, "Most biased y shards (one standard deviation is {:.5}):", per_y_sd)?;
+        for (y, bias) in per_y.iter().take(8) {
+            writeln!(out, "  y == {:#010X}: worst bit bias {:.5}", y, bias)?;
+        }
+    }
+
+    for target in targets.targets() {
+        let work = analysis.estimated_work(target.high, target.low, targets.mask());
+        let ideal = targets.mask().count_ones() as f64;
+        // 64 free bits are searched, so this many solutions should exist
+        writeln!(out, "Estimated work for {}: 2^{:.2} candidates (ideal 2^{:.0}), about 2^{:.2} solutions in the free words",
+                 target.label, work, ideal, INPUT_BITS as f64 - work)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use crate::rom::{ipl3_checksum, HeaderCrc};
+
+pub struct Cic {
+    pub name: &'static str,
+    pub seed: u32,
+    /// The 48-bit checksum of the retail IPL3 for this CIC.
+    pub checksum: u64,
+    /// How IPL3 checks the header CRCs for this CIC.
+    pub crc: HeaderCrc,
+}
+
+// 6105/7105 is missing on purpose: its IPL2 mixes extra words into the
+// checksum, which ChecksumInfo doesn't implement
+pub const CICS: &[Cic] = &[
+    Cic { name: "6101", seed: 0x3F, checksum: 0x45CC_73EE_317A, crc: HeaderCrc::Cic6102 },
+    Cic { name: "6102", seed: 0x3F, checksum: 0xA536_C0F1_D859, crc: HeaderCrc::Cic6102 },
+    Cic { name: "7101", seed: 0x3F, checksum: 0xA536_C0F1_D859, crc: HeaderCrc::Cic6102 },
+    Cic { name: "7102", seed: 0x3F, checksum: 0x4416_0EC5_D9AF, crc: HeaderCrc::Cic6102 },
+    Cic { name: "6103", seed: 0x78, checksum: 0x586F_D470_9867, crc: HeaderCrc::Cic6103 },
+    Cic { name: "7103", seed: 0x78, checksum: 0x586F_D470_9867, crc: HeaderCrc::Cic6103 },
+    Cic { name: "6106", seed: 0x85, checksum: 0x2BBA_D4E6_EB74, crc: HeaderCrc::Cic6106 },
+    Cic { name: "7106", seed: 0x85, checksum: 0x2BBA_D4E6_EB74, crc: HeaderCrc::Cic6106 },
+];
+
+pub fn by_name(name: &str) -> Option<&'static Cic> {
+    let name = name.trim_start_matches("CIC-").trim_start_matches("cic-");
+    CICS.iter().find(|cic| cic.name == name)
+}
+
+/// Finds the CICs whose retail IPL3 checksum `rom` reproduces. Regional twins
+/// such as 6102 and 7101 share an IPL3, so more than one can match.
+pub fn detect(rom: [u8; 4096]) -> Vec<&'static Cic> {
+    CICS.iter().filter(|cic| {
+        let (high, low) = ipl3_checksum(cic.seed, rom);
+        ((high as u64) << 32) | (low as u64) == cic.checksum
+    }).collect()
+}
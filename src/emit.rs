@@ -0,0 +1,82 @@
+use sha2::{Digest, Sha256};
+
+use crate::search::Hit;
+use crate::target::Target;
+
+/// The ways found words can be written out for a build to pick up instead of
+/// patching the binary afterwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WordsFormat {
+    /// GNU as `.word` directives.
+    Asm,
+    /// A C header with named constants.
+    C,
+    /// The 8 raw bytes to link at 0xFF8. There's nowhere to put comments.
+    Bin,
+}
+
+impl WordsFormat {
+    pub fn from_path(path: &str) -> Option<WordsFormat> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?;
+        match extension {
+            "s" | "S" | "asm" => Some(WordsFormat::Asm),
+            "h" => Some(WordsFormat::C),
+            "bin" => Some(WordsFormat::Bin),
+            _ => None,
+        }
+    }
+}
+
+/// The SHA-256 of the image the words were searched for, so a build can tell
+/// when its IPL3 has changed and the words are stale.
+pub fn source_hash(rom: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(rom))
+}
+
+pub struct Words<'a> {
+    pub hit: Hit,
+    pub seed: u32,
+    /// Every target the words match.
+    pub targets: &'a [Target],
+    pub source_hash: String,
+}
+
+impl<'a> Words<'a> {
+    fn comment_lines(&self) -> Vec<String> {
+        let mut lines = vec!["IPL3 checksum collision words, generated by ipl3hasher".to_string()];
+        lines.extend(self.targets.iter().map(|target| format!("target checksum: {}", target)));
+        lines.push(format!("seed: {:#04X}", self.seed));
+        lines.push(format!("source image sha256: {}", self.source_hash));
+        lines
+    }
+
+    pub fn render(&self, format: WordsFormat) -> Vec<u8> {
+        let Hit { y, x } = self.hit;
+        match format {
+            WordsFormat::Asm => {
+                let mut text: String = self.comment_lines().iter().map(|line| format!("# {}\n", line)).collect();
+                text += &format!("    .word {:#010X}  # y, at 0xFF8\n", y);
+                text += &format!("    .word {:#010X}  # x, at 0xFFC\n", x);
+                text.into_bytes()
+            }
+            WordsFormat::C => {
+                let mut text = String::from("/*\n");
+                for line in self.comment_lines() {
+                    text += &format!(" * {}\n", line);
+                }
+                text += " */\n";
+                text += "#ifndef IPL3_COLLISION_H\n#define IPL3_COLLISION_H\n\n";
+                text += &format!("#define IPL3_COLLISION_Y {:#010X}u /* at 0xFF8 */\n", y);
+                text += &format!("#define IPL3_COLLISION_X {:#010X}u /* at 0xFFC */\n", x);
+                text += "\n#endif\n";
+                text.into_bytes()
+            }
+            WordsFormat::Bin => {
+                let mut bytes = Vec::with_capacity(8);
+                bytes.extend_from_slice(&y.to_be_bytes());
+                bytes.extend_from_slice(&x.to_be_bytes());
+                bytes
+            }
+        }
+    }
+}
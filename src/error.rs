@@ -0,0 +1,113 @@
+use std::fmt;
+use std::io;
+
+// Exit codes, so a script can tell a search that came up empty from one that
+// never got going.
+
+/// The search range held no solution.
+pub const EXIT_NOT_FOUND: i32 = 1;
+/// A file or option was unusable.
+pub const EXIT_BAD_INPUT: i32 = 2;
+/// A search backend failed.
+pub const EXIT_BACKEND: i32 = 3;
+/// A ROM was read fine but failed a check, such as verify's.
+pub const EXIT_CHECK_FAILED: i32 = 4;
+/// A budget ran out before the end of the search range.
+pub const EXIT_BUDGET_EXCEEDED: i32 = 5;
+/// The search was cancelled before the end of its range.
+pub const EXIT_CANCELLED: i32 = 6;
+/// Progress or results couldn't be written to the console.
+pub const EXIT_OUTPUT: i32 = 7;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A file couldn't be read or written.
+    Io { path: String, source: io::Error },
+    /// A file was read but can't be used, such as a malformed patch.
+    Format { path: String, message: String },
+    /// A file is shorter than it has to be.
+    TooShort { path: String, len: usize, expected: usize },
+    /// Writing progress or results to the console failed.
+    Output(io::Error),
+    /// The options can't be acted on.
+    Options(String),
+    /// A search backend failed.
+    Backend(String),
+    /// The search range held no solution.
+    NotFound,
+    /// A budget ran out with every y before `resume` searched.
+    BudgetExceeded { resume: u64 },
+    /// The search was cancelled with every y before `resume` searched.
+    Cancelled { resume: u64 },
+    /// A ROM failed a check.
+    Check(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// For `map_err`, naming the file an I/O error came from.
+    pub fn io(path: &str) -> impl FnOnce(io::Error) -> Error {
+        let path = path.to_string();
+        move |source| Error::Io { path, source }
+    }
+
+    /// For `map_err`, naming the file an `InvalidData` error came from.
+    pub fn format(path: &str) -> impl FnOnce(io::Error) -> Error {
+        let path = path.to_string();
+        move |e| Error::Format { path, message: e.to_string() }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NotFound => EXIT_NOT_FOUND,
+            Error::BudgetExceeded { .. } => EXIT_BUDGET_EXCEEDED,
+            Error::Cancelled { .. } => EXIT_CANCELLED,
+            Error::Io { .. } | Error::Format { .. } | Error::TooShort { .. } | Error::Options(_) => EXIT_BAD_INPUT,
+            Error::Output(_) => EXIT_OUTPUT,
+            Error::Backend(_) => EXIT_BACKEND,
+            Error::Check(_) => EXIT_CHECK_FAILED,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::Format { path, message } => write!(f, "{}: {}", path, message),
+            Error::TooShort { path, len, expected } => {
+                write!(f, "{} is only {} bytes, expected at least {}", path, len, expected)
+            }
+            Error::Output(e) => write!(f, "can't write output: {}", e),
+            Error::Options(message) => write!(f, "{}", message),
+            Error::Backend(message) => write!(f, "search backend failed: {}", message),
+            Error::NotFound => write!(f, "no solution in the search range"),
+            Error::BudgetExceeded { resume } => {
+                write!(f, "the budget ran out before the end of the search range, resume with --init {:#X}", resume)
+            }
+            Error::Cancelled { resume } => {
+                write!(f, "the search was cancelled before the end of its range, resume with --init {:#X}", resume)
+            }
+            Error::Check(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Output(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Bare I/O errors come from writing to the console; file errors are mapped
+/// with `Error::io` so they carry a path.
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Output(e)
+    }
+}
@@ -0,0 +1,504 @@
+use byteorder::BigEndian;
+use emu_core::prelude::*;
+use emu_glsl::*;
+use emu_core::device::DeviceFnMut;
+use std::io::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::checksum::ChecksumInfo;
+use crate::error::Error;
+use crate::hybrid::Backend;
+use crate::report::{Event, Progress, Reporter};
+use crate::search::{midstate, near_miss_summary, y_midstate, Hit, Outcome, SearchOptions};
+use crate::target::TargetSet;
+
+const NEAR_MISS_LOG: usize = 64;
+
+pub struct GpuOptions {
+    pub threads: u32,
+    pub groups: u32,
+    /// Print each range of hashes as it's sent to the GPU.
+    pub verbose: bool,
+}
+
+impl Default for GpuOptions {
+    fn default() -> GpuOptions {
+        GpuOptions {
+            threads: 0x400,
+            groups: 0x20000,
+            verbose: false,
+        }
+    }
+}
+
+/// Searches on the GPU for the hybrid orchestrator, which calls it once per
+/// run of y. The kernel is compiled on the first call and kept for as long
+/// as later ones search for the same thing.
+pub struct GpuBackend {
+    pub gpu: GpuOptions,
+    kernel: Option<(KernelKey, Arc<DeviceFnMut>)>,
+}
+
+impl GpuBackend {
+    pub fn new(gpu: GpuOptions) -> GpuBackend {
+        GpuBackend { gpu, kernel: None }
+    }
+}
+
+impl Backend for GpuBackend {
+    fn name(&self) -> &str {
+        "gpu"
+    }
+
+    fn search(&mut self, source_rom: [u8; 4096], targets: &TargetSet, opts: &SearchOptions, out: &mut Reporter) -> crate::error::Result<Outcome> {
+        let pre_csum = midstate(opts.seed, source_rom);
+        let key = KernelKey::new(&pre_csum, targets, opts, &self.gpu);
+        let kernel = match &self.kernel {
+            Some((compiled_for, kernel)) if *compiled_for == key => kernel.clone(),
+            _ => {
+                let kernel = compile_kernel(&key, out).map_err(gpu_error)?;
+                self.kernel = Some((key, kernel.clone()));
+                kernel
+            }
+        };
+        run_kernel(&kernel, &pre_csum, targets, opts, &self.gpu, out).map_err(gpu_error)
+    }
+}
+
+/// Failing to write the log is an output error like anywhere else; anything
+/// else went wrong on the device.
+fn gpu_error(e: Box<dyn std::error::Error>) -> Error {
+    match e.downcast::<std::io::Error>() {
+        Ok(e) => Error::Output(*e),
+        Err(e) => Error::Backend(e.to_string()),
+    }
+}
+
+pub fn gpu_search(source_rom: [u8; 4096], targets: &TargetSet, opts: &SearchOptions, gpu: &GpuOptions, out: &mut Reporter) -> crate::error::Result<Outcome> {
+    let pre_csum = midstate(opts.seed, source_rom);
+    let kernel = compile_kernel(&KernelKey::new(&pre_csum, targets, opts, gpu), out).map_err(gpu_error)?;
+    run_kernel(&kernel, &pre_csum, targets, opts, gpu, out).map_err(gpu_error)
+}
+
+/// Everything the kernel is compiled for. The y, x and range bounds are
+/// passed per launch, so one kernel serves any range of the same search.
+#[derive(Clone, PartialEq)]
+struct KernelKey {
+    threads: u32,
+    seed: u32,
+    /// The word before the free words, which the last two rounds read.
+    data_prev: u32,
+    deterministic: bool,
+    keys: Vec<u64>,
+    mask: u64,
+    near_miss: bool,
+    near_keys: Vec<u64>,
+    near_mask: u64,
+}
+
+impl KernelKey {
+    fn new(pre_csum: &ChecksumInfo<BigEndian>, targets: &TargetSet, opts: &SearchOptions, gpu: &GpuOptions) -> KernelKey {
+        let near_targets = near_targets(targets, opts);
+        KernelKey {
+            threads: gpu.threads,
+            seed: opts.seed,
+            data_prev: pre_csum.rom_word(1021),
+            deterministic: opts.deterministic,
+            keys: targets.keys().to_vec(),
+            mask: targets.mask(),
+            near_miss: opts.near_miss_mask.is_some(),
+            near_keys: near_targets.keys().to_vec(),
+            near_mask: near_targets.mask(),
+        }
+    }
+}
+
+// without a near-miss mask the table is a dummy and the kernel skips it
+fn near_targets(targets: &TargetSet, opts: &SearchOptions) -> TargetSet {
+    TargetSet::with_mask(targets.targets().to_vec(), opts.near_miss_mask.unwrap_or(0))
+}
+
+fn compile_kernel(key: &KernelKey, out: &mut Reporter) -> Result<Arc<DeviceFnMut>, Box<dyn std::error::Error>> {
+    // ensure that a device pool has been initialized
+    // this should be called before every time when you assume you have devices to use
+    // that goes for both library users and application users
+    futures::executor::block_on(assert_device_pool_initialized());
+
+    writeln!(out, "{:?}", take()?.lock().unwrap().info.as_ref().unwrap())?;
+
+    // the masked targets are sorted, so the kernel can binary search them
+    let target_count = key.keys.len();
+    let target_his: Vec<String> = key.keys.iter().map(|k| format!("{}u", k >> 32)).collect();
+    let target_los: Vec<String> = key.keys.iter().map(|k| format!("{}u", *k as u32)).collect();
+    let near_count = key.near_keys.len();
+    let near_his: Vec<String> = key.near_keys.iter().map(|k| format!("{}u", k >> 32)).collect();
+    let near_los: Vec<String> = key.near_keys.iter().map(|k| format!("{}u", *k as u32)).collect();
+
+    // compile GslKernel to SPIR-V
+    // then, we can either inspect the SPIR-V or finish the compilation by generating a DeviceFnMut
+    // then, run the DeviceFnMut
+    let kernel = GlslKernel::new()
+    .spawn(key.threads)
+    .param::<[u32], _>("uint[16] state_in")
+    .param_mut::<u32, _>("uint x_offset")
+    .param_mut::<u32, _>("uint y_offset")
+    .param_mut::<u32, _>("uint x_last")
+    .param_mut::<[u32], _>("uint[1] finished")
+    .param_mut::<[u32], _>("uint[2] result")
+    .param_mut::<[u32], _>(format!("uint[{}] near_misses", 1 + NEAR_MISS_LOG))
+    .with_const("uint magic", "0x95DACFDC")
+    .with_const("int target_count", format!("{}", target_count))
+    .with_const(format!("uint target_hi[{}]", target_count), format!("uint[{}]({})", target_count, target_his.join(", ")))
+    .with_const(format!("uint target_lo[{}]", target_count), format!("uint[{}]({})", target_count, target_los.join(", ")))
+    .with_const("uint mask_hi", format!("{}u", key.mask >> 32))
+    .with_const("uint mask_lo", format!("{}u", key.mask as u32))
+    .with_const("uint data_prev", format!("{}u", key.data_prev))
+    .with_const("bool near_miss_enabled", format!("{}", key.near_miss))
+    .with_const("uint near_miss_log", format!("{}u", NEAR_MISS_LOG))
+    .with_const("int near_count", format!("{}", near_count))
+    .with_const(format!("uint near_hi[{}]", near_count), format!("uint[{}]({})", near_count, near_his.join(", ")))
+    .with_const(format!("uint near_lo[{}]", near_count), format!("uint[{}]({})", near_count, near_los.join(", ")))
+    .with_const("uint near_mask_hi", format!("{}u", key.near_mask >> 32))
+    .with_const("uint near_mask_lo", format!("{}u", key.near_mask as u32))
+    .with_const("uint seed", format!("{}", key.seed))
+    .with_const("bool deterministic", format!("{}", key.deterministic))
+.with_helper_code(r#"
+uint csum(uint op1, uint op2, uint op3) {
+    uint hi;
+    uint lo;
+    if (op2 == 0) {
+        op2 = op3;
+    }
+
+    umulExtended(op1, op2, hi, lo);
+
+    if (hi - lo == 0) {
+        return op1;
+    }
+
+    return hi - lo;
+}
+
+uint[16] round(uint[16] state, uint data_last, uint data, uint data_next, uint loop_count) {
+    state[0] += csum(uint(0x3EF - loop_count), data, loop_count);
+    state[1] = csum(state[1], data, loop_count);
+    state[2] ^= data;
+    state[3] += csum(data + 5, 0x6c078965, loop_count);
+
+    if (data_last < data) {
+        state[9] = csum(state[9], data, loop_count);
+    }
+    else {
+        state[9] += data;
+    }
+
+    state[4] += ((data << (0x20 - (data_last & 0x1f))) | (data >> (data_last & 0x1f)));
+    state[7] = csum(state[7], ((data >> (0x20 - (data_last & 0x1f))) | (data << (data_last & 0x1f))), loop_count);
+
+    if (data < state[6]) {
+        state[6] = (data + loop_count) ^ (state[3] + state[6]);
+    }
+    else {
+        state[6] = (state[4] + data) ^ state[6];
+    }
+
+    state[5] += (data >> (0x20 - (data_last >> 27))) | (data << (data_last >> 27));
+    state[8] = csum(state[8], (data << (0x20 - (data_last >> 27))) | (data >> (data_last >> 27)), loop_count);
+
+    if (loop_count == 0x3F0) return state;
+
+    uint tmp1 = csum(state[15], (data >> (0x20 - (data_last >> 27))) | (data << (data_last >> 27)), loop_count);
+    state[15] = csum(tmp1, (data_next << (data >> 27)) | (data_next >> (0x20 - (data >> 27))), loop_count);
+
+    uint tmp2 = ((data << (0x20 - (data_last & 0x1f))) | (data >> (data_last & 0x1f)));
+    uint tmp3 = csum(state[14], tmp2, loop_count);
+    uint tmp4 = csum(tmp3, (data_next >> (data & 0x1f)) | (data_next << (0x20 - (data & 0x1f))), loop_count);
+
+    state[14] = tmp4;
+    state[13] += ((data >> (data & 0x1f)) | (data << (0x20 - (data & 0x1f)))) + ((data_next >> (data_next & 0x1f)) | (data_next << (0x20 - (data_next & 0x1f))));
+    state[10] = csum(state[10] + data, data_next, loop_count);
+    state[11] = csum(state[11] ^ data, data_next, loop_count);
+    state[12] += (state[8] ^ data);
+
+    return state;
+}
+
+uint[2] finalize(uint[16] state) {
+    uint buf[4];
+
+    for (int i = 0; i < 4; i++) {
+        buf[i] = state[0];
+    }
+
+    for (uint i = 0; i < 16; i++) {
+        uint data = state[i];
+        uint shift = data & 0x1f;
+        uint data_shifted_left = data << (32 - shift);
+        uint data_shifted_right = data >> shift;
+        uint tmp = buf[0] + (data_shifted_right | data_shifted_left);
+        buf[0] = tmp;
+
+        if (data < tmp) {
+            buf[1] += data;
+        }
+        else {
+            buf[1] = csum(buf[1], data, i);
+        }
+
+        tmp = (data & 0x02) >> 1;
+        uint tmp2 = data & 0x01;
+
+        if (tmp == tmp2) {
+            buf[2] += data;
+        }
+        else {
+            buf[2] = csum(buf[2], data, i);
+        }
+
+        if (tmp2 == 1) {
+            buf[3] ^= data;
+        }
+        else {
+            buf[3] = csum(buf[3], data, i);
+        }
+    }
+
+    uint res[2];
+    res[1] = csum(buf[0], buf[1], 16) & 0xFFFF;
+    res[0] = buf[3] ^ buf[2];
+    return res;
+}
+
+bool is_target(uint hi, uint lo) {
+    int first = 0;
+    int last = target_count - 1;
+    while (first <= last) {
+        int mid = (first + last) / 2;
+        if (target_hi[mid] == hi && target_lo[mid] == lo) {
+            return true;
+        }
+
+        if (target_hi[mid] < hi || (target_hi[mid] == hi && target_lo[mid] < lo)) {
+            first = mid + 1;
+        }
+        else {
+            last = mid - 1;
+        }
+    }
+
+    return false;
+}
+
+bool is_near(uint hi, uint lo) {
+    int first = 0;
+    int last = near_count - 1;
+    while (first <= last) {
+        int mid = (first + last) / 2;
+        if (near_hi[mid] == hi && near_lo[mid] == lo) {
+            return true;
+        }
+
+        if (near_hi[mid] < hi || (near_hi[mid] == hi && near_lo[mid] < lo)) {
+            first = mid + 1;
+        }
+        else {
+            last = mid - 1;
+        }
+    }
+
+    return false;
+}
+
+uint[2] crunch(uint[16] state_in, uint hi, uint lo) {
+    uint state[16];
+    for (int i = 0; i < 16; i++) {
+        state[i] = state_in[i];
+    }
+
+    uint data_last = data_prev;
+    uint data = hi;
+    uint data_next = lo;
+    uint loop_count = 1007;
+
+    state = round(state, data_last, data, data_next, loop_count);
+
+    data_last = data;
+    data = data_next;
+    data_next = 0;
+    loop_count = 1008;
+
+    state = round(state, data_last, data, data_next, loop_count);
+
+    return finalize(state);
+}
+"#)
+.with_kernel_code(
+r#"
+    // the last launch for a y may run past the end of the range
+    if (gl_GlobalInvocationID.x > x_last - x_offset) {
+        return;
+    }
+
+    uint y = y_offset;
+    uint x = x_offset + gl_GlobalInvocationID.x;
+    uint local_result[2] = crunch(state_in, y, x);
+    if (near_miss_enabled && is_near(local_result[1] & near_mask_hi, local_result[0] & near_mask_lo)) {
+        uint slot = atomicAdd(near_misses[0], 1);
+        if (slot < near_miss_log) {
+            near_misses[1 + slot] = x;
+        }
+    }
+    if (is_target(local_result[1] & mask_hi, local_result[0] & mask_lo)) {
+        if (deterministic) {
+            // every hit in this launch shares y, so the minimum x wins
+            atomicOr(finished[0], 1);
+            atomicMin(result[0], x);
+            result[1] = y;
+        }
+        else if (atomicOr(finished[0], 1) == 0) {
+            result[0] = x;
+            result[1] = y;
+        }
+    }
+"#,
+);
+    Ok(compile::<GlslKernel, GlslKernelCompile, Vec<u32>, GlobalCache>(kernel)?.finish()?)
+}
+
+fn run_kernel(
+    c: &Arc<DeviceFnMut>,
+    pre_csum: &ChecksumInfo<BigEndian>,
+    targets: &TargetSet,
+    opts: &SearchOptions,
+    gpu: &GpuOptions,
+    out: &mut Reporter,
+) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let near_targets = near_targets(targets, opts);
+    if opts.x_end == 0 {
+        return Ok(Outcome::Exhausted);
+    }
+
+    // create some data on GPU
+    // even mutate it once loaded to GPU
+    //let mut state: DeviceBox<[u32]> = vec![0; 16].as_device_boxed_mut()?;
+    // result[0] starts at u32::MAX so deterministic mode can atomicMin into it
+    let mut res: DeviceBox<[u32]> = vec![u32::MAX, 0u32].as_device_boxed_mut()?;
+    let mut y_off_src = opts.init as u64;
+    let mut x_off: DeviceBox<u32> = 0u32.into_device_boxed_mut()?;
+    let mut y_off: DeviceBox<u32> = (y_off_src as u32).into_device_boxed_mut()?;
+    let mut x_last: DeviceBox<u32> = ((opts.x_end - 1) as u32).into_device_boxed_mut()?;
+    let mut finished: DeviceBox<[u32]> = vec![0u32].as_device_boxed_mut()?;
+    // near_misses[0] counts every near miss, the first NEAR_MISS_LOG x values follow
+    let mut near_misses: DeviceBox<[u32]> = vec![0u32; 1 + NEAR_MISS_LOG].as_device_boxed_mut()?;
+
+    let mut finished_src = false;
+    let search_start = Instant::now();
+    let mut tried = 0u64;
+    let mut stopped = None;
+    let range_candidates = (opts.y_end - opts.init as u64) as f64 * opts.x_end as f64;
+    while y_off_src < opts.y_end {
+        stopped = opts.stop_reason(search_start, tried);
+        if stopped.is_none() && opts.budget.y_count.is_some_and(|limit| y_off_src - opts.init as u64 >= limit) {
+            stopped = Some(Outcome::BudgetExceeded);
+        }
+        if stopped.is_some() {
+            break;
+        }
+
+        let mut x_off_src = 0u64;
+        x_off.set(x_off_src as u32)?;
+        let y_csum = y_midstate(pre_csum, y_off_src as u32);
+        let state_vec: Vec<u32> = y_csum.buffer.to_vec();
+        let state_in: DeviceBox<[u32]> = state_vec.as_device_boxed()?;
+        let start = Instant::now();
+        out.event(&Event::RangeStarted { y: y_off_src as u32, x_start: 0, x_end: opts.x_end })?;
+        loop {
+            let bump = (gpu.threads as u64) * (gpu.groups as u64);
+            if gpu.verbose {
+                out.write_fmt(format_args!(
+                    "should calc from ({}, {}) to ({}, {}) in {} threads on {} workgroups\n",
+                    x_off_src,
+                    y_off_src,
+                    x_off_src + bump,
+                    y_off_src,
+                    gpu.threads,
+                    gpu.groups
+                ))?;
+            }
+            unsafe {
+                spawn(gpu.groups).launch(call!(
+                    c.clone(),
+                    &state_in,
+                    &mut x_off,
+                    &mut y_off,
+                    &mut x_last,
+                    &mut finished,
+                    &mut res,
+                    &mut near_misses
+                ))?;
+            }
+            finished_src = futures::executor::block_on(finished.get())?[0] == 1;
+            if finished_src {
+                break;
+            }
+
+            tried += bump.min(opts.x_end - x_off_src);
+            x_off_src += bump;
+            let rate = tried as f64 / search_start.elapsed().as_secs_f64();
+            let eta = Duration::try_from_secs_f64((range_candidates - tried as f64) / rate).ok();
+            out.progress(&Progress { tried, y: y_off_src as u32, x: x_off_src.min(opts.x_end), rate, eta })?;
+
+            if x_off_src >= opts.x_end {
+                break;
+            }
+
+            // checked between launches, so a search stops within one
+            stopped = opts.stop_reason(search_start, tried);
+            if stopped.is_some() {
+                break;
+            }
+
+            x_off.set(x_off_src as u32)?;
+        }
+        let duration = start.elapsed();
+        writeln!(out, "Inner loop Y=={} took {:?}", y_off_src, duration)?;
+        out.event(&Event::RangeFinished { y: y_off_src as u32, seconds: duration.as_secs_f64(), complete: !finished_src && stopped.is_none() })?;
+
+        if opts.near_miss_mask.is_some() {
+            let near_log = futures::executor::block_on(near_misses.get())?;
+            let near_total = near_log[0] as u64;
+            for x in near_log[1..].iter().take(near_total as usize) {
+                writeln!(out, "Near miss at {:#X}, {:#X}", y_off_src, x)?;
+            }
+            if near_total > NEAR_MISS_LOG as u64 {
+                writeln!(out, "({} more near misses not logged)", near_total - NEAR_MISS_LOG as u64)?;
+            }
+
+            // a y that ended in a hit or was stopped early was cut short,
+            // so there's no meaningful candidate count to compare against
+            if !finished_src && stopped.is_none() {
+                writeln!(out, "Y=={}: {}", y_off_src,
+                       near_miss_summary(near_total, opts.x_end, &near_targets))?;
+            }
+            near_misses = vec![0u32; 1 + NEAR_MISS_LOG].as_device_boxed_mut()?;
+        }
+        out.flush()?;
+        //return Ok(());
+
+        if finished_src || stopped.is_some() {
+            break;
+        }
+
+        y_off_src += 1;
+        y_off.set(y_off_src as u32)?;
+    }
+
+    // download from GPU
+    if finished_src {
+        let result = futures::executor::block_on(res.get())?;
+        Ok(Outcome::Found(Hit { y: result[1], x: result[0] }))
+    } else {
+        Ok(stopped.unwrap_or(Outcome::Exhausted))
+    }
+}
@@ -0,0 +1,485 @@
+use std::io::prelude::*;
+use std::ops::Range;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::report::{Progress, Reporter};
+use crate::search::{cpu_search, Budget, CancelToken, CpuOptions, Hit, Outcome, SearchOptions};
+use crate::target::TargetSet;
+
+/// Roughly how long a backend should spend on each run of y it takes, once
+/// its speed is known.
+const CHUNK_SECONDS: f64 = 10.0;
+
+/// Something that can search a range of y, for running several side by side.
+pub trait Backend: Send {
+    fn name(&self) -> &str;
+
+    /// Searches `opts.init..opts.y_end` by `0..opts.x_end`, honouring its
+    /// cancel token and budget and reporting progress through `out`.
+    fn search(&mut self, source_rom: [u8; 4096], targets: &TargetSet, opts: &SearchOptions, out: &mut Reporter) -> Result<Outcome>;
+}
+
+pub struct CpuBackend {
+    pub cpu: CpuOptions,
+}
+
+impl Backend for CpuBackend {
+    fn name(&self) -> &str {
+        "cpu"
+    }
+
+    fn search(&mut self, source_rom: [u8; 4096], targets: &TargetSet, opts: &SearchOptions, out: &mut Reporter) -> Result<Outcome> {
+        cpu_search(source_rom, targets, opts, &self.cpu, out)
+    }
+}
+
+/// Adds `range` to a sorted list of disjoint ranges, merging where they meet.
+fn insert(ranges: &mut Vec<Range<u64>>, range: Range<u64>) {
+    if range.is_empty() {
+        return;
+    }
+
+    let at = ranges.partition_point(|r| r.start < range.start);
+    ranges.insert(at, range);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for r in ranges.drain(..) {
+        match merged.last_mut() {
+            Some(last) if last.end >= r.start => last.end = last.end.max(r.end),
+            _ => merged.push(r),
+        }
+    }
+    *ranges = merged;
+}
+
+/// Which y of a search have been covered, by whom, and which are still to
+/// hand out. A y counts as covered once every x in it has been tried. A
+/// ledger left by a stopped search can be passed to another to carry on.
+#[derive(Clone, Debug)]
+pub struct Ledger {
+    y_start: u64,
+    y_end: u64,
+    x_end: u64,
+    untaken: Vec<Range<u64>>,
+    covered: Vec<Range<u64>>,
+    /// Candidates tried, by backend.
+    tried: Vec<u64>,
+}
+
+impl Ledger {
+    pub fn new(y_start: u64, y_end: u64, x_end: u64) -> Ledger {
+        let mut untaken = Vec::new();
+        insert(&mut untaken, y_start..y_end);
+        Ledger { y_start, y_end, x_end, untaken, covered: Vec::new(), tried: Vec::new() }
+    }
+
+    pub fn x_end(&self) -> u64 {
+        self.x_end
+    }
+
+    /// The y searched in full, as sorted, disjoint ranges.
+    pub fn covered(&self) -> &[Range<u64>] {
+        &self.covered
+    }
+
+    /// The y nobody has searched in full, as sorted, disjoint ranges.
+    pub fn untaken(&self) -> &[Range<u64>] {
+        &self.untaken
+    }
+
+    /// The first y that isn't covered, or the end of the range.
+    pub fn covered_up_to(&self) -> u64 {
+        match self.covered.first() {
+            Some(first) if first.start == self.y_start => first.end,
+            _ => self.y_start,
+        }
+    }
+
+    /// Candidates backend `backend`, by its position in the list given to
+    /// `hybrid_search`, has tried.
+    pub fn tried_by(&self, backend: usize) -> u64 {
+        self.tried.get(backend).copied().unwrap_or(0)
+    }
+
+    pub fn tried(&self) -> u64 {
+        self.tried.iter().sum()
+    }
+
+    /// Hands out up to `count` y from the first untaken run, if it starts
+    /// before `below`, stopping short of `below`.
+    fn take(&mut self, count: u64, below: u64) -> Option<Range<u64>> {
+        let first = self.untaken.first_mut().filter(|first| first.start < below)?;
+        let range = first.start..(first.start + count).min(first.end).min(below);
+        first.start = range.end;
+        if first.is_empty() {
+            self.untaken.remove(0);
+        }
+        Some(range)
+    }
+
+    /// Records that `backend` covered `done`, tried `tried` candidates doing
+    /// so and any it got to past there, and gave back the rest of `taken`.
+    fn settle(&mut self, backend: usize, taken: Range<u64>, done: u64, tried: u64) {
+        if self.tried.len() <= backend {
+            self.tried.resize(backend + 1, 0);
+        }
+        self.tried[backend] += tried;
+        insert(&mut self.covered, taken.start..done);
+        insert(&mut self.untaken, done..taken.end);
+    }
+}
+
+/// A run of y a backend is working through.
+struct Running {
+    ys: Range<u64>,
+    cancel: CancelToken,
+    /// Candidates tried so far.
+    tried: u64,
+    /// The most it may try under the candidate budget.
+    capacity: u64,
+}
+
+/// Everything the backend threads share, behind one lock.
+struct State<'l> {
+    ledger: &'l mut Ledger,
+    running: Vec<Option<Running>>,
+    /// Candidates per second, by backend, once measured.
+    rates: Vec<Option<f64>>,
+    hits: Vec<Hit>,
+    stopped: Option<Outcome>,
+    /// y handed out by this search and not given back.
+    handed: u64,
+    /// What the ledger had tried when this search began.
+    base: u64,
+    errors: Vec<Error>,
+}
+
+impl<'l> State<'l> {
+    fn tried(&self) -> u64 {
+        self.ledger.tried() - self.base + self.running.iter().flatten().map(|running| running.tried).sum::<u64>()
+    }
+
+    /// How many y backend `backend` should take next: a single y until its
+    /// speed is known, then as many as it gets through in `CHUNK_SECONDS`,
+    /// but no more than its share of what's left by speed.
+    fn chunk_size(&self, backend: usize) -> u64 {
+        let rate = match self.rates[backend] {
+            Some(rate) => rate,
+            None => return 1,
+        };
+
+        let known: Vec<f64> = self.rates.iter().flatten().copied().collect();
+        // backends yet to be measured are counted as average
+        let total = known.iter().sum::<f64>() * self.rates.len() as f64 / known.len() as f64;
+        let untaken: u64 = self.ledger.untaken().iter().map(|r| r.end - r.start).sum();
+        let by_time = rate * CHUNK_SECONDS / self.ledger.x_end() as f64;
+        let by_share = (untaken as f64 * rate / total).ceil();
+        by_time.min(by_share).max(1.0) as u64
+    }
+
+    /// The next run of y for `backend`, with the token to cancel it and the
+    /// budget to search it under, or None if there's nothing for it to do.
+    fn next_chunk(&mut self, backend: usize, opts: &SearchOptions, search_start: Instant) -> Option<(Range<u64>, CancelToken, Budget)> {
+        if self.stopped.is_some() || opts.cancel.is_cancelled() || (!self.hits.is_empty() && !opts.deterministic) {
+            return None;
+        }
+        // with a hit, only y before it can hold a smaller one
+        let below = self.hits.iter().map(|hit| hit.y as u64).min().unwrap_or(u64::MAX);
+        if self.ledger.untaken().first().is_none_or(|first| first.start >= below) {
+            return None;
+        }
+
+        let x_end = self.ledger.x_end();
+        let mut count = self.chunk_size(backend);
+        let mut budget = Budget::default();
+        let mut capacity = u64::MAX;
+        let mut over = false;
+        if let Some(limit) = opts.budget.duration {
+            match limit.checked_sub(search_start.elapsed()) {
+                Some(left) if !left.is_zero() => budget.duration = Some(left),
+                _ => over = true,
+            }
+        }
+        if let Some(limit) = opts.budget.y_count {
+            let left = limit.saturating_sub(self.handed);
+            over |= left == 0;
+            count = count.min(left);
+        }
+        if let Some(limit) = opts.budget.candidates {
+            let committed = self.ledger.tried() - self.base
+                + self.running.iter().flatten().map(|running| running.capacity).sum::<u64>();
+            let left = limit.saturating_sub(committed);
+            over |= left == 0;
+            count = count.min(left.div_ceil(x_end));
+            budget.candidates = Some(left);
+            capacity = left;
+        }
+        if over {
+            self.stopped = Some(Outcome::BudgetExceeded);
+            return None;
+        }
+
+        let ys = self.ledger.take(count, below)?;
+        self.handed += ys.end - ys.start;
+        let capacity = capacity.min((ys.end - ys.start).saturating_mul(x_end));
+        let cancel = CancelToken::default();
+        self.running[backend] = Some(Running { ys: ys.clone(), cancel: cancel.clone(), tried: 0, capacity });
+        Some((ys, cancel, budget))
+    }
+
+    /// Books a finished run of y, whatever became of it. `last` is the last
+    /// progress the backend reported on it.
+    fn settle(&mut self, backend: usize, result: &Result<Outcome>, last: Option<Progress>, seconds: f64, deterministic: bool) {
+        let running = self.running[backend].take().unwrap();
+        let x_end = self.ledger.x_end();
+        let done = match (result, last) {
+            (Ok(Outcome::Exhausted), _) => running.ys.end,
+            // everything before the progress position was searched
+            (_, Some(last)) => (last.y as u64 + (last.x >= x_end) as u64).clamp(running.ys.start, running.ys.end),
+            (_, None) => running.ys.start,
+        };
+        let tried = last.map_or(0, |last| last.tried);
+        self.ledger.settle(backend, running.ys.clone(), done, tried);
+        self.handed -= running.ys.end - done;
+
+        if tried > 0 && seconds > 0.0 {
+            let rate = tried as f64 / seconds;
+            self.rates[backend] = Some(self.rates[backend].map_or(rate, |old| (old + rate) / 2.0));
+        }
+
+        match result {
+            Ok(Outcome::Found(hit)) => {
+                self.hits.push(*hit);
+                // later runs can't beat this hit, and without
+                // --deterministic nothing needs to
+                for other in self.running.iter().flatten() {
+                    if !deterministic || other.ys.start > hit.y as u64 {
+                        other.cancel.cancel();
+                    }
+                }
+            }
+            Ok(Outcome::BudgetExceeded) => {
+                self.stopped.get_or_insert(Outcome::BudgetExceeded);
+            }
+            _ => {}
+        }
+    }
+
+    fn cancel_all(&self) {
+        for running in self.running.iter().flatten() {
+            running.cancel.cancel();
+        }
+    }
+}
+
+/// What the backend threads tell the thread running the search.
+enum Message {
+    Log(usize, String),
+    Progress,
+    Settled { backend: usize, ys: Range<u64>, seconds: f64, tried: u64 },
+    Failed { backend: usize, error: String },
+}
+
+/// Passes a backend's log on a line at a time.
+struct LogLines {
+    backend: usize,
+    line: Vec<u8>,
+    messages: Sender<Message>,
+}
+
+impl Write for LogLines {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                // the search is over if nobody's listening
+                let _ = self.messages.send(Message::Log(self.backend, line));
+                self.line.clear();
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Takes runs of y from the ledger for one backend until there are none left
+/// for it, or it fails.
+#[allow(clippy::too_many_arguments)]
+fn run_backend(
+    backend: usize,
+    searcher: &mut dyn Backend,
+    source_rom: [u8; 4096],
+    targets: &TargetSet,
+    opts: &SearchOptions,
+    state: &Mutex<State>,
+    messages: Sender<Message>,
+    search_start: Instant,
+) {
+    loop {
+        let (chunk, others_running) = {
+            let mut state = state.lock().unwrap();
+            (state.next_chunk(backend, opts, search_start), state.running.iter().any(Option::is_some))
+        };
+        let (ys, cancel, budget) = match chunk {
+            Some(chunk) => chunk,
+            // another backend may yet fail and give work back
+            None if others_running => {
+                std::thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+            None => return,
+        };
+        let chunk_opts = SearchOptions {
+            init: ys.start as u32,
+            y_end: ys.end,
+            x_end: opts.x_end,
+            cancel,
+            budget,
+            ..opts.clone()
+        };
+
+        let start = Instant::now();
+        let mut last = None;
+        let log = LogLines { backend, line: Vec::new(), messages: messages.clone() };
+        let progress_messages = messages.clone();
+        let mut out = Reporter::new(Box::new(log)).on_progress(|progress: &Progress| {
+            last = Some(*progress);
+            if let Some(running) = &mut state.lock().unwrap().running[backend] {
+                running.tried = progress.tried;
+            }
+            let _ = progress_messages.send(Message::Progress);
+        });
+        let result = searcher.search(source_rom, targets, &chunk_opts, &mut out);
+        drop(out);
+
+        let seconds = start.elapsed().as_secs_f64();
+        state.lock().unwrap().settle(backend, &result, last, seconds, opts.deterministic);
+        let tried = last.map_or(0, |last| last.tried);
+        match result {
+            Ok(_) => {
+                let _ = messages.send(Message::Settled { backend, ys, seconds, tried });
+            }
+            Err(e) => {
+                let _ = messages.send(Message::Failed { backend, error: e.to_string() });
+                state.lock().unwrap().errors.push(e);
+                return;
+            }
+        }
+    }
+}
+
+/// Runs every backend in `backends` at once over the y still untaken in
+/// `ledger`, with `opts.x_end` x each, until a hit, the end of the range, a
+/// cancellation or a budget. A backend that fails has its work handed to
+/// the others; its error is only returned if none are left to finish.
+///
+/// Backends log through `out` a line at a time, tagged with their name, and
+/// its progress covers them all.
+pub fn hybrid_search(
+    source_rom: [u8; 4096],
+    targets: &TargetSet,
+    opts: &SearchOptions,
+    backends: &mut [Box<dyn Backend>],
+    ledger: &mut Ledger,
+    out: &mut Reporter,
+) -> Result<Outcome> {
+    if opts.x_end != ledger.x_end() {
+        return Err(Error::Options(format!("the ledger is for {} x per y, not {}", ledger.x_end(), opts.x_end)));
+    }
+
+    let names: Vec<String> = backends.iter().enumerate().map(|(i, b)| format!("{} {}", b.name(), i)).collect();
+    let search_start = Instant::now();
+    let range_candidates = ledger.untaken().iter().map(|r| (r.end - r.start) as f64).sum::<f64>() * opts.x_end as f64;
+    let base = ledger.tried();
+    let state = Mutex::new(State {
+        ledger,
+        running: backends.iter().map(|_| None).collect(),
+        rates: vec![None; backends.len()],
+        hits: Vec::new(),
+        stopped: None,
+        handed: 0,
+        base,
+        errors: Vec::new(),
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    let reported = std::thread::scope(|scope| {
+        for (backend, searcher) in backends.iter_mut().enumerate() {
+            let messages = sender.clone();
+            let state = &state;
+            scope.spawn(move || {
+                run_backend(backend, searcher.as_mut(), source_rom, targets, opts, state, messages, search_start)
+            });
+        }
+        drop(sender);
+
+        loop {
+            let message = match receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    if opts.cancel.is_cancelled() {
+                        state.lock().unwrap().cancel_all();
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+
+            let written = match message {
+                Message::Log(backend, line) => writeln!(out, "[{}] {}", names[backend], line),
+                Message::Settled { backend, ys, seconds, tried } => {
+                    writeln!(out, "[{}] searched y {:#X}..{:#X} in {:.1}s, {:.2} MH/s",
+                             names[backend], ys.start, ys.end, seconds, tried as f64 / seconds / 1e6)
+                }
+                Message::Failed { backend, error } => {
+                    writeln!(out, "[{}] failed, handing its work to the others: {}", names[backend], error)
+                }
+                Message::Progress => {
+                    let state = state.lock().unwrap();
+                    let tried = state.tried();
+                    let covered = state.ledger.covered_up_to();
+                    let (y, x) = if covered < state.ledger.y_end {
+                        (covered as u32, 0)
+                    } else {
+                        ((covered - 1) as u32, opts.x_end)
+                    };
+                    let rate = state.rates.iter().flatten().sum();
+                    let pace = tried as f64 / search_start.elapsed().as_secs_f64();
+                    let eta = Duration::try_from_secs_f64((range_candidates - tried as f64) / pace).ok();
+                    drop(state);
+                    out.progress(&Progress { tried: base + tried, y, x, rate, eta })
+                }
+            };
+            if let Err(e) = written {
+                // stops the backends; their sends fail once this returns
+                let mut state = state.lock().unwrap();
+                state.stopped = Some(Outcome::Cancelled);
+                state.cancel_all();
+                return Err(Error::Output(e));
+            }
+        }
+    });
+    reported?;
+
+    let state = state.into_inner().unwrap();
+    if let Some(hit) = state.hits.into_iter().min_by_key(|hit| (hit.y, hit.x)) {
+        return Ok(Outcome::Found(hit));
+    }
+    if let Some(stopped) = state.stopped {
+        return Ok(stopped);
+    }
+    if opts.cancel.is_cancelled() {
+        return Ok(Outcome::Cancelled);
+    }
+    match state.errors.into_iter().next() {
+        Some(e) if !state.ledger.untaken().is_empty() => Err(e),
+        _ => Ok(Outcome::Exhausted),
+    }
+}
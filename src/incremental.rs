@@ -0,0 +1,93 @@
+use byteorder::BigEndian;
+
+use crate::checksum::ChecksumInfo;
+
+/// The first word the rounds read; everything before it is header.
+const FIRST_WORD: usize = 16;
+
+/// Keeps the state every `interval` rounds, so that after editing a word only
+/// the rounds from the last unaffected snapshot onwards have to run again.
+pub struct IncrementalHasher {
+    seed: u32,
+    interval: u32,
+    csum: ChecksumInfo<BigEndian>,
+    /// `snapshots[i]` is the state after `i * interval` rounds.
+    snapshots: Vec<[u32; 16]>,
+    /// How many leading snapshots are still up to date.
+    valid: usize,
+}
+
+impl IncrementalHasher {
+    pub fn new(seed: u32, rom: [u8; 4096], interval: u32) -> IncrementalHasher {
+        let interval = interval.max(1);
+        IncrementalHasher {
+            seed,
+            interval,
+            csum: ChecksumInfo::new(seed, rom),
+            snapshots: Vec::with_capacity((1008 / interval) as usize + 1),
+            valid: 0,
+        }
+    }
+
+    /// Reads a word by its absolute index.
+    pub fn word(&self, idx: usize) -> u32 {
+        self.csum.rom_word(idx)
+    }
+
+    pub fn rom(&self) -> &[u8; 4096] {
+        &self.csum.rom
+    }
+
+    /// Changes a word by its absolute index, dropping the snapshots it affects.
+    /// Panics unless `idx` is below 1024.
+    pub fn set_word(&mut self, idx: usize, value: u32) {
+        assert!(idx < 1024, "word {} is past the end of the IPL3, which has 1024", idx);
+        if self.word(idx) == value {
+            return;
+        }
+        self.csum.set_rom_word(idx, value);
+
+        if idx < FIRST_WORD {
+            return;
+        }
+        // word idx is first read as data_next in round idx - 16, so the state
+        // after round idx - 17 is the last one it can't have touched
+        let untouched = idx.saturating_sub(FIRST_WORD + 1) as u32;
+        let still_valid = if idx == FIRST_WORD {
+            // the first word also seeds the initial state
+            0
+        } else {
+            (untouched / self.interval) as usize + 1
+        };
+        self.valid = self.valid.min(still_valid);
+    }
+
+    /// Returns (high, low), running only the rounds after the last snapshot
+    /// that's still up to date.
+    pub fn checksum(&mut self) -> (u32, u32) {
+        if self.valid == 0 {
+            let fresh: ChecksumInfo<BigEndian> = ChecksumInfo::new(self.seed, self.csum.rom);
+            self.csum.buffer = fresh.buffer;
+            self.snapshots.clear();
+            self.snapshots.push(self.csum.buffer);
+            self.valid = 1;
+        }
+
+        self.snapshots.truncate(self.valid);
+        let mut round = (self.valid as u32 - 1) * self.interval;
+        self.csum.buffer = self.snapshots[self.valid - 1];
+        while round < 1008 {
+            let next = (round + self.interval).min(1008);
+            self.csum.checksum(round, next);
+            // a short last step doesn't land on a snapshot
+            if next == round + self.interval {
+                self.snapshots.push(self.csum.buffer);
+            }
+            round = next;
+        }
+        self.valid = self.snapshots.len();
+
+        self.csum.finalize_checksum();
+        (self.csum.high, self.csum.low)
+    }
+}
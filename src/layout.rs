@@ -0,0 +1,138 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+
+// Lays a freshly built IPL3 out into the boot region of a ROM. IPL2 copies
+// ROM 0x40..0x1000 to the same offsets in SP DMEM, so a segment linked at
+// 0xA4000040 lands at ROM 0x40.
+
+pub const IPL3_START: usize = 0x40;
+pub const IPL3_END: usize = 0x1000;
+/// Where the free words start; payloads have to stop short of it.
+pub const FREE_WORDS: usize = 4088;
+
+const SP_DMEM: u64 = 0x0400_0000;
+
+pub struct Segment {
+    /// Offset into the ROM.
+    pub offset: usize,
+    pub data: Vec<u8>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn truncated() -> Error {
+    invalid("ELF file is truncated".to_string())
+}
+
+fn field<E: ByteOrder>(bytes: &[u8], at: u64, size: usize) -> std::io::Result<u64> {
+    let at = usize::try_from(at).map_err(|_| truncated())?;
+    let field = at.checked_add(size).and_then(|end| bytes.get(at..end)).ok_or_else(truncated)?;
+    Ok(match size {
+        2 => E::read_u16(field) as u64,
+        4 => E::read_u32(field) as u64,
+        _ => E::read_u64(field),
+    })
+}
+
+fn elf_segments<E: ByteOrder>(bytes: &[u8], wide: bool) -> std::io::Result<Vec<Segment>> {
+    const PT_LOAD: u64 = 1;
+
+    // offsets of the fields used in ELF32 and ELF64 respectively
+    let word = if wide { 8 } else { 4 };
+    let (phoff, phentsize, phnum) = if wide { (0x20, 0x36, 0x38) } else { (0x1C, 0x2A, 0x2C) };
+    let (p_offset, p_vaddr, p_filesz) = if wide { (0x08, 0x10, 0x20) } else { (0x04, 0x08, 0x10) };
+
+    let table = field::<E>(bytes, phoff, word)?;
+    let entry_size = field::<E>(bytes, phentsize, 2)?;
+    let count = field::<E>(bytes, phnum, 2)?;
+
+    let mut segments = Vec::new();
+    for i in 0..count {
+        // a header past the end of the file, however far, is just truncation
+        let header = i.checked_mul(entry_size).and_then(|at| at.checked_add(table)).ok_or_else(truncated)?;
+        let header_field = |at: u64| header.checked_add(at).ok_or_else(truncated).and_then(|at| field::<E>(bytes, at, word));
+        if field::<E>(bytes, header, 4)? != PT_LOAD {
+            continue;
+        }
+
+        let offset = header_field(p_offset)?;
+        let vaddr = header_field(p_vaddr)?;
+        let filesz = header_field(p_filesz)?;
+        if filesz == 0 {
+            continue;
+        }
+
+        // DMEM may be addressed through KSEG0 or KSEG1, so go by physical address
+        let physical = vaddr & 0x1FFF_FFFF;
+        if physical < SP_DMEM + IPL3_START as u64 || physical >= SP_DMEM + IPL3_END as u64 {
+            return Err(invalid(format!("ELF segment at {:#010X} isn't in the IPL3 part of SP DMEM", vaddr)));
+        }
+
+        let data = offset.checked_add(filesz)
+            .and_then(|end| bytes.get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+            .ok_or_else(|| invalid("ELF segment runs past the end of the file".to_string()))?;
+        segments.push(Segment {
+            offset: (physical - SP_DMEM) as usize,
+            data: data.to_vec(),
+        });
+    }
+
+    if segments.is_empty() {
+        return Err(invalid("ELF file has nothing to load".to_string()));
+    }
+    Ok(segments)
+}
+
+/// Reads an IPL3 as an ELF file, or failing that as a flat binary starting at
+/// 0x40.
+pub fn read_payload(bytes: &[u8]) -> std::io::Result<Vec<Segment>> {
+    if !bytes.starts_with(b"\x7FELF") {
+        return Ok(vec![Segment { offset: IPL3_START, data: bytes.to_vec() }]);
+    }
+
+    let wide = match bytes.get(4) {
+        Some(1) => false,
+        Some(2) => true,
+        _ => return Err(invalid("unknown ELF class".to_string())),
+    };
+    match bytes.get(5) {
+        Some(1) => elf_segments::<LittleEndian>(bytes, wide),
+        Some(2) => elf_segments::<BigEndian>(bytes, wide),
+        _ => Err(invalid("unknown ELF byte order".to_string())),
+    }
+}
+
+/// Replaces the IPL3 of a big-endian `template` with `segments`, zeroing
+/// everything else up to 0x1000. The header and anything past the boot region
+/// are kept.
+pub fn lay_out(template: &[u8], segments: &[Segment]) -> std::io::Result<Vec<u8>> {
+    if template.len() < IPL3_START {
+        return Err(invalid("the template is too short to hold a header".to_string()));
+    }
+
+    let mut rom = template.to_vec();
+    if rom.len() < IPL3_END {
+        rom.resize(IPL3_END, 0);
+    }
+    rom[IPL3_START..IPL3_END].iter_mut().for_each(|b| *b = 0);
+
+    for segment in segments {
+        let end = segment.offset.checked_add(segment.data.len()).filter(|&end| end <= IPL3_END);
+        let end = match end {
+            Some(end) if segment.offset >= IPL3_START => end,
+            _ => {
+                return Err(invalid(format!("IPL3 code at {:#X} of {:#X} bytes doesn't fit in 0x40..0x1000",
+                                           segment.offset, segment.data.len())))
+            }
+        };
+        if end > FREE_WORDS {
+            return Err(invalid(format!("IPL3 code at {:#X}..{:#X} overlaps the free words at {:#X}",
+                                       segment.offset, end, FREE_WORDS)));
+        }
+        rom[segment.offset..end].copy_from_slice(&segment.data);
+    }
+    Ok(rom)
+}
@@ -0,0 +1,20 @@
+pub mod analysis;
+pub use ipl3_core::checksum;
+pub mod cic;
+pub mod emit;
+pub mod error;
+pub mod gpu;
+pub mod hybrid;
+pub mod incremental;
+pub mod layout;
+pub mod mips;
+pub mod patch;
+pub mod reach;
+pub mod report;
+pub mod rom;
+pub mod schedule;
+pub mod search;
+pub mod solver;
+pub use ipl3_core::source;
+pub mod target;
+pub mod trace;
@@ -1,347 +1,911 @@
-use emu_core::prelude::*;
-use emu_glsl::*;
-//use zerocopy::*;
-use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder};
+use gumdrop::Options;
 use rand::Rng;
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::Read;
-
+use std::io::Write;
 use std::time::{Duration, Instant};
 
-mod cpu;
-use cpu::*;
+use ipl3::analysis;
+use ipl3::cic;
+use ipl3::emit::{source_hash, Words, WordsFormat};
+use ipl3::error::Error;
+use ipl3::gpu::{gpu_search, GpuBackend, GpuOptions};
+use ipl3::hybrid::{hybrid_search, Backend, CpuBackend, Ledger};
+use ipl3::layout::{lay_out, read_payload};
+use ipl3::mips::{self, Safety};
+use ipl3::patch::{apply_patch, make_bps, make_ips};
+use ipl3::reach;
+use ipl3::report::{Event, Reporter};
+use ipl3::rom::{
+    fix_header_crc, header_crc, ipl3_checksum, ipl3_of, load_rom, patched_checksum, read_ipl3, set_free_words, stored_crc,
+    RomFormat, CRC_LENGTH, CRC_START,
+};
+use ipl3::search::{cpu_search, midstate, Budget, CpuOptions, Hit, Outcome, SearchOptions};
+use ipl3::source::{source_checksum, MappedFile, OutOfBounds, Swapped};
+use ipl3::target::{Target, TargetSet, FULL_MASK};
+use ipl3::trace;
+
+// Every number on the command line is decimal, or hex with an explicit 0x
+// prefix. Underscores may be used as digit separators in either.
+fn parse_u64(s: &str) -> Result<u64, String> {
+    let digits = s.replace('_', "");
+    let parsed = if digits.starts_with("0x") || digits.starts_with("0X") {
+        u64::from_str_radix(&digits[2..], 16)
+    } else {
+        digits.parse()
+    };
 
-use gumdrop::Options;
+    parsed.map_err(|e| format!("bad number {:?}: {}", s, e))
+}
 
-fn parse_hex(s: &str) -> Result<u16, std::num::ParseIntError> {
-    u16::from_str_radix(s, 16)
+fn parse_u32(s: &str) -> Result<u32, String> {
+    let n = parse_u64(s)?;
+    if n > u32::MAX as u64 {
+        return Err(format!("{:?} doesn't fit in 32 bits", s));
+    }
+
+    Ok(n as u32)
 }
 
-fn parse_hex_u32(s: &str) -> Result<u32, std::num::ParseIntError> {
-    u32::from_str_radix(s, 16)
+fn parse_seed(s: &str) -> Result<u32, String> {
+    let n = parse_u32(s)?;
+    if n > 0xFF {
+        return Err(format!("seed {:?} doesn't fit in 8 bits", s));
+    }
+
+    Ok(n)
+}
+
+fn parse_percent(s: &str) -> Result<u8, String> {
+    let n = parse_u64(s)?;
+    if !(1..=100).contains(&n) {
+        return Err(format!("{:?} isn't a percentage from 1 to 100", s));
+    }
+
+    Ok(n as u8)
+}
+
+/// A 48-bit checksum or a mask over one.
+fn parse_checksum(s: &str) -> Result<u64, String> {
+    let n = parse_u64(s)?;
+    if n >> 48 != 0 {
+        return Err(format!("{:?} is wider than 48 bits", s));
+    }
+
+    Ok(n)
+}
+
+/// Parses a `LABEL=CHECKSUM` target.
+fn parse_target(s: &str) -> Result<Target, String> {
+    let mut parts = s.splitn(2, '=');
+    let label = parts.next().unwrap_or("");
+    let csum = parts.next().ok_or_else(|| format!("expected LABEL=CHECKSUM, got {:?}", s))?;
+    let csum = parse_checksum(csum)?;
+
+    Ok(Target {
+        label: label.to_string(),
+        high: (csum >> 32) as u32,
+        low: csum as u32,
+    })
 }
 
 #[derive(Debug, Options)]
-struct CSumOptions {
+struct Args {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(command)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Options)]
+enum Command {
+    #[options(help = "Print the IPL3 checksum of ROMs for one or more seeds or CICs")]
+    Hash(HashOptions),
+    #[options(help = "Identify the CIC a ROM's IPL3 was made for")]
+    Detect(DetectOptions),
+    #[options(help = "Check whether a ROM's IPL3 can execute, load or store its free words")]
+    Scan(ScanOptions),
+    #[options(help = "Print the checksum state after every round")]
+    Trace(TraceOptions),
+    #[options(help = "Find the round where the checksum states of two ROMs part ways")]
+    Diff(DiffOptions),
+    #[options(help = "Search for free words that give a ROM the target checksum")]
+    Search(SearchArgs),
+    #[options(help = "Write a pair of free words into a ROM, or a patch that does")]
+    Patch(PatchOptions),
+    #[options(help = "Check a ROM's IPL3 checksum and header CRCs")]
+    Verify(VerifyOptions),
+    #[options(help = "Apply an IPS or BPS patch and check the result")]
+    Apply(ApplyOptions),
+    #[options(help = "Measure the hash rate of a backend")]
+    Bench(BenchOptions),
+}
+
+#[derive(Debug, Options)]
+struct HashOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, required, help = "The ROMs or IPL3 images to hash")]
+    roms: Vec<String>,
+    #[options(help = "A seed to hash with, may be repeated (default: 0x3F)", parse(try_from_str = "parse_seed"))]
+    seed: Vec<u32>,
+    #[options(no_short, help = "Hash with the seed of this CIC, e.g. 6102, may be repeated")]
+    cic: Vec<String>,
+    #[options(help = "Hash with the seed of every known CIC")]
+    all: bool,
+    #[options(
+        no_short,
+        help = "Print one tab-separated FILE SEED CIC CHECKSUM line per hash, with - for no CIC"
+    )]
+    tsv: bool,
+}
+
+#[derive(Debug, Options)]
+struct DetectOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, required, help = "The ROM to identify")]
+    rom: String,
+}
+
+#[derive(Debug, Options)]
+struct TraceOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, required, help = "The ROM to trace, in any byte order")]
+    rom: String,
+    #[options(default = "0x3F", help = "The seed value, unless given by --cic", parse(try_from_str = "parse_seed"))]
+    seed: u32,
+    #[options(no_short, help = "Use the seed of this CIC, e.g. 6102")]
+    cic: Option<String>,
+    #[options(default = "0", help = "The first round to print, 0 for the initial state", parse(try_from_str = "parse_u32"))]
+    from: u32,
+    #[options(default = "1008", help = "The last round to print", parse(try_from_str = "parse_u32"))]
+    to: u32,
+}
+
+#[derive(Debug, Options)]
+struct DiffOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, required, help = "The first ROM, in any byte order")]
+    a: String,
+    #[options(free, required, help = "The second ROM, in any byte order")]
+    b: String,
+    #[options(default = "0x3F", help = "The seed value, unless given by --cic", parse(try_from_str = "parse_seed"))]
+    seed: u32,
+    #[options(no_short, help = "Use the seed of this CIC, e.g. 6102")]
+    cic: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct ScanOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, required, help = "The ROM to scan, in any byte order")]
+    rom: String,
+}
+
+#[derive(Debug, Options)]
+struct SearchArgs {
+    #[options(help = "Print this help message")]
+    help: bool,
     #[options(free, required, help = "The ROM whose checksum must be matched")]
     golden: String,
+    #[options(free, required, help = "The seed value for the hash", parse(try_from_str = "parse_seed"))]
+    seed: u32,
+    #[options(free, required, help = "The ROM to be modified")]
+    source: String,
+    #[options(default = "0", help = "The Y coordinate to start with", parse(try_from_str = "parse_u32"))]
+    init: u32,
+    #[options(help = "Always report the smallest matching X for each Y")]
+    deterministic: bool,
     #[options(
-        free,
-        required,
-        help = "The seed value for the hash",
-        parse(try_from_str = "parse_hex")
+        no_short,
+        help = "An additional LABEL=CHECKSUM target to accept, may be repeated",
+        parse(try_from_str = "parse_target")
     )]
-    seed: u16,
-    #[options(free, help = "The ROM to be modified")]
-    source: String,
+    target: Vec<Target>,
     #[options(
-        default = "400",
-        help = "The number of threads to use",
-        parse(try_from_str = "parse_hex_u32")
+        no_short,
+        default = "0xFFFFFFFFFFFF",
+        help = "Only require the checksum bits set in this 48-bit mask to match",
+        parse(try_from_str = "parse_checksum")
     )]
-    threads: u32,
+    match_mask: u64,
+    #[options(help = "Write the patched source ROM here once a match is found")]
+    output: Option<String>,
     #[options(
-        default = "20000",
-        help = "The number of groups to use",
-        parse(try_from_str = "parse_hex_u32")
+        no_short,
+        help = "Replace the source ROM's IPL3 with this ELF or flat binary before searching"
     )]
-    groups: u32,
-    #[options(default = "0", help = "The Y coordinate to start with")]
-    init: u32,
+    payload: Option<String>,
     #[options(
-        short = "v",
-        default = "false",
-        help = "Print each range of hashes as they're sent to the GPU"
+        no_short,
+        help = "Write the found words to this .s, .h or .bin file once a match is found, may be repeated"
     )]
+    emit: Vec<String>,
+    #[options(no_short, help = "Print JSON-line events on stdout and the log on stderr")]
+    json: bool,
+    #[options(
+        no_short,
+        help = "Log candidates matching a target on the bits of this 48-bit mask",
+        parse(try_from_str = "parse_checksum")
+    )]
+    near_miss_mask: Option<u64>,
+    #[options(no_short, help = "Measure bias and avalanche of the final rounds instead of searching")]
+    analyze: bool,
+    #[options(no_short, default = "65536", help = "The number of samples to analyze", parse(try_from_str = "parse_u64"))]
+    samples: u64,
+    #[options(no_short, default = "16", help = "The number of Y values to spread the samples over", parse(try_from_str = "parse_u64"))]
+    shards: u64,
+    #[options(no_short, help = "Experimental: ask z3 for X before brute forcing each Y (needs the smt feature)")]
+    smt: bool,
+    #[options(no_short, default = "10000", help = "Milliseconds the solver may spend on each Y", parse(try_from_str = "parse_u64"))]
+    smt_timeout: u64,
+    #[options(no_short, help = "Give up after this many seconds", parse(try_from_str = "parse_u64"))]
+    max_time: Option<u64>,
+    #[options(no_short, help = "Give up after searching this many Y values", parse(try_from_str = "parse_u64"))]
+    max_y: Option<u64>,
+    #[options(no_short, help = "Give up after trying this many candidates", parse(try_from_str = "parse_u64"))]
+    max_candidates: Option<u64>,
+    #[options(no_short, default = "0", help = "The number of CPU worker threads, 0 for one per core", parse(try_from_str = "parse_u64"))]
+    workers: u64,
+    #[options(no_short, help = "Pin each CPU worker to a core")]
+    pin: bool,
+    #[options(no_short, help = "Run the CPU workers at the lowest priority")]
+    background: bool,
+    #[options(no_short, default = "100", help = "Cap CPU workers to this percentage of the time", parse(try_from_str = "parse_percent"))]
+    throttle: u8,
+    #[options(no_short, help = "Search on the GPU instead of the CPU")]
+    gpu: bool,
+    #[options(no_short, help = "Search on the CPU and the GPU at once")]
+    hybrid: bool,
+    #[options(default = "1024", help = "The number of GPU threads per workgroup", parse(try_from_str = "parse_u32"))]
+    threads: u32,
+    #[options(default = "131072", help = "The number of GPU workgroups per launch", parse(try_from_str = "parse_u32"))]
+    groups: u32,
+    #[options(short = "v", help = "Print each range of hashes as they're sent to the GPU")]
     verbose: bool,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let opts = CSumOptions::parse_args_default_or_exit();
-    let target_high;
-    let target_low;
-    if let Ok(mut file) = File::open(opts.golden) {
-        let mut rom: [u8; 4096] = [0; 4096];
-        if let Ok(_) = file.read_exact(&mut rom) {
-            let mut target_csum: ChecksumInfo<BigEndian> = ChecksumInfo::new(opts.seed as u32, rom);
-            target_csum.checksum(0, 1008);
-            target_csum.finalize_checksum();
-            target_high = target_csum.high;
-            target_low = target_csum.low;
-        } else {
-            panic!();
-        }
-    } else {
-        panic!();
-    }
+#[derive(Debug, Options)]
+struct PatchOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, required, help = "The ROM to be modified")]
+    source: String,
+    #[options(free, required, help = "The Y word to write at 0xFF8", parse(try_from_str = "parse_u32"))]
+    y: u32,
+    #[options(free, required, help = "The X word to write at 0xFFC", parse(try_from_str = "parse_u32"))]
+    x: u32,
+    #[options(help = "Where to write the patched ROM")]
+    output: Option<String>,
+    #[options(no_short, help = "Write an IPS patch from the source ROM to the patched one")]
+    ips: Option<String>,
+    #[options(no_short, help = "Write a BPS patch from the source ROM to the patched one")]
+    bps: Option<String>,
+    #[options(no_short, help = "Also rewrite the header CRCs for this CIC, e.g. 6102")]
+    fix_crc: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct VerifyOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, required, help = "The ROM to check, in any byte order")]
+    rom: String,
+    #[options(no_short, help = "Check against the retail checksum and header CRCs of this CIC, e.g. 6102")]
+    cic: Option<String>,
+    #[options(help = "Check against the checksum of this ROM")]
+    golden: Option<String>,
+    #[options(
+        no_short,
+        help = "Check against a LABEL=CHECKSUM target, may be repeated",
+        parse(try_from_str = "parse_target")
+    )]
+    target: Vec<Target>,
+    #[options(default = "0x3F", help = "The seed value, unless given by --cic", parse(try_from_str = "parse_seed"))]
+    seed: u32,
+}
 
-    println!("Target checksum: {:#06X} {:08X}", target_high, target_low);
+#[derive(Debug, Options)]
+struct ApplyOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, required, help = "The ROM to patch, in any byte order")]
+    rom: String,
+    #[options(free, required, help = "The IPS or BPS patch to apply")]
+    patch: String,
+    #[options(help = "Where to write the patched ROM once it checks out")]
+    output: Option<String>,
+    #[options(no_short, help = "Check against the retail checksum and header CRCs of this CIC, e.g. 6102")]
+    cic: Option<String>,
+    #[options(help = "Check against the checksum of this ROM")]
+    golden: Option<String>,
+    #[options(
+        no_short,
+        help = "Check against a LABEL=CHECKSUM target, may be repeated",
+        parse(try_from_str = "parse_target")
+    )]
+    target: Vec<Target>,
+    #[options(default = "0x3F", help = "The seed value, unless given by --cic", parse(try_from_str = "parse_seed"))]
+    seed: u32,
+}
 
-    let mut pre_csum: ChecksumInfo<BigEndian>;
+#[derive(Debug, Options)]
+struct BenchOptions {
+    #[options(help = "Print this help message")]
+    help: bool,
+    #[options(free, help = "The ROM to hash, random data if not given")]
+    source: Option<String>,
+    #[options(default = "0x3F", help = "The seed value for the hash", parse(try_from_str = "parse_seed"))]
+    seed: u32,
+    #[options(default = "16777216", help = "The number of candidates to try", parse(try_from_str = "parse_u64"))]
+    candidates: u64,
+    #[options(no_short, default = "0", help = "The number of CPU worker threads, 0 for one per core", parse(try_from_str = "parse_u64"))]
+    workers: u64,
+    #[options(no_short, help = "Benchmark the GPU instead of the CPU")]
+    gpu: bool,
+    #[options(default = "1024", help = "The number of GPU threads per workgroup", parse(try_from_str = "parse_u32"))]
+    threads: u32,
+    #[options(default = "131072", help = "The number of GPU workgroups per launch", parse(try_from_str = "parse_u32"))]
+    groups: u32,
+}
 
-    if let Ok(mut file) = File::open(opts.source) {
-        let mut rom: [u8; 4096] = [0; 4096];
-        if let Ok(_) = file.read_exact(&mut rom) {
-            pre_csum = ChecksumInfo::new(opts.seed as u32, rom);
-            pre_csum.checksum(0, 1005);
-        } else {
-            panic!();
-        }
-    } else {
-        panic!();
+fn hash(opts: HashOptions) -> Result<(), Error> {
+    // each entry is a seed and the CIC it was picked for, if any
+    let mut seeds: Vec<(u32, Option<&cic::Cic>)> = opts.seed.iter().map(|&seed| (seed, None)).collect();
+    for name in &opts.cic {
+        let cic = cic_named(name)?;
+        seeds.push((cic.seed, Some(cic)));
+    }
+    if opts.all {
+        seeds.extend(cic::CICS.iter().map(|cic| (cic.seed, Some(cic))));
+    }
+    if seeds.is_empty() {
+        seeds.push((0x3F, None));
     }
 
-    // ensure that a device pool has been initialized
-    // this should be called before every time when you assume you have devices to use
-    // that goes for both library users and application users
-    futures::executor::block_on(assert_device_pool_initialized());
-
-    println!("{:?}", take()?.lock().unwrap().info.as_ref().unwrap());
+    for path in &opts.roms {
+        let too_short = |e: OutOfBounds| Error::TooShort { path: path.to_string(), len: e.len, expected: 4096 };
+        let mut rom = Swapped::detect(MappedFile::open(path).map_err(Error::io(path))?).map_err(too_short)?;
+        for &(seed, cic) in &seeds {
+            let (high, low) = source_checksum(seed, &mut rom).map_err(too_short)?;
+            let checksum = ((high as u64) << 32) | (low as u64);
 
-    // create some data on GPU
-    // even mutate it once loaded to GPU
-    //let mut state: DeviceBox<[u32]> = vec![0; 16].as_device_boxed_mut()?;
-    let mut res: DeviceBox<[u32]> = vec![0u32, 0u32].as_device_boxed_mut()?;
-    let mut x_off_src = 0u64;
-    let mut y_off_src = opts.init as u64;
-    let mut x_off: DeviceBox<u32> = 0u32.into_device_boxed_mut()?;
-    let mut y_off: DeviceBox<u32> = 0u32.into_device_boxed_mut()?;
-    let mut finished: DeviceBox<[u32]> = vec![0u32].as_device_boxed_mut()?;
+            if opts.tsv {
+                println!("{}\t{:#04X}\t{}\t{:012X}", path, seed, cic.map_or("-", |cic| cic.name), checksum);
+                continue;
+            }
 
-    // compile GslKernel to SPIR-V
-    // then, we can either inspect the SPIR-V or finish the compilation by generating a DeviceFnMut
-    // then, run the DeviceFnMut
-    let kernel = GlslKernel::new()
-    .spawn(opts.threads)
-    .param::<[u32], _>("uint[16] state_in")
-    .param_mut::<u32, _>("uint x_offset")
-    .param_mut::<u32, _>("uint y_offset")
-    .param_mut::<[u32], _>("uint[1] finished")
-    .param_mut::<[u32], _>("uint[2] result")
-    .with_const("uint magic", "0x95DACFDC")
-    .with_const("uint target_hi", format!("{}", target_high))
-    .with_const("uint target_lo", format!("{}", target_low))
-    .with_const("uint seed", format!("{}", opts.seed as u32))
-.with_helper_code(r#"
-uint csum(uint op1, uint op2, uint op3) {
-    uint hi;
-    uint lo;
-    if (op2 == 0) {
-        op2 = op3;
+            match cic {
+                Some(cic) => {
+                    let retail = if cic.checksum == checksum { ", matches retail" } else { "" };
+                    println!("{}: {:#06X} {:08X} (CIC-NUS-{}, seed {:#04X}{})", path, high, low, cic.name, seed, retail);
+                }
+                None => println!("{}: {:#06X} {:08X} (seed {:#04X})", path, high, low, seed),
+            }
+        }
     }
+    Ok(())
+}
 
-    umulExtended(op1, op2, hi, lo);
-
-    if (hi - lo == 0) {
-        return lo;
+fn detect(opts: DetectOptions) -> Result<(), Error> {
+    let rom = read_ipl3(&opts.rom)?;
+    let cics = cic::detect(rom);
+    if cics.is_empty() {
+        return Err(Error::Check(format!("{} doesn't match any known CIC", opts.rom)));
     }
 
-    return hi - lo;
+    for cic in cics {
+        println!("CIC-NUS-{} (seed {:#04X})", cic.name, cic.seed);
+    }
+    Ok(())
 }
 
-uint[16] round(uint[16] state, uint data_last, uint data, uint data_next, uint loop_count) {
-    state[0] += csum(uint(0x3EF - loop_count), data, loop_count);
-    state[1] = csum(state[1], data, loop_count);
-    state[2] ^= data;
-    state[3] += csum(data + 5, 0x6c078965, loop_count);
-
-    if (data_last < data) {
-        state[9] = csum(state[9], data, loop_count);
+/// Warns if the IPL3 of a big-endian `rom` may touch its free words, and if it
+/// may run them, whether the words in place are safe to run.
+fn report_reachability<W: Write>(out: &mut W, rom: &[u8]) -> std::io::Result<()> {
+    let reach = reach::scan(rom);
+    writeln!(out, "IPL3 scan: {} reachable instructions", reach.executed.len())?;
+    if !reach.exits.is_empty() {
+        let exits: Vec<String> = reach.exits.iter().map(|pc| format!("{:#010X}", pc)).collect();
+        writeln!(out, "  leaves DMEM for {}", exits.join(", "))?;
     }
-    else {
-        state[9] += data;
+    if !reach.unresolved_jumps.is_empty() || !reach.unresolved_accesses.is_empty() {
+        writeln!(out, "  {} jumps and {} loads or stores go through unknown addresses, so the scan may miss paths",
+                 reach.unresolved_jumps.len(), reach.unresolved_accesses.len())?;
     }
 
-    state[4] += ((data << (0x20 - (data_last & 0x1f))) | (data >> (data_last & 0x1f)));
-    state[7] = csum(state[7], ((data >> (0x20 - (data_last & 0x1f))) | (data << (data_last & 0x1f))), loop_count);
+    if !reach.free_words_reachable() {
+        writeln!(out, "The free words are never executed, loaded or stored")?;
+        return Ok(());
+    }
 
-    if (data < state[6]) {
-        state[6] = (data + loop_count) ^ (state[3] + state[6]);
+    for (offset, mnemonic) in &reach.references {
+        writeln!(out, "Warning: the {} at {:#05X} may access the free words", mnemonic, offset)?;
     }
-    else {
-        state[6] = (state[4] + data) ^ state[6];
+    if reach.free_words_executed() {
+        writeln!(out, "Warning: the free words may be executed")?;
+        for (offset, name) in [(reach::FREE_WORDS, "y"), (reach::FREE_WORDS + 4, "x")].iter() {
+            let word = BigEndian::read_u32(&rom[*offset..]);
+            let pc = reach::ENTRY - 0x40 + *offset as u32;
+            let mnemonic = mips::decode(word, pc).mnemonic;
+            match mips::safety(word, pc) {
+                Safety::NoEffect => writeln!(out, "  {} = {:#010X} ({}) has no effect", name, word, mnemonic)?,
+                Safety::WritesRegister(reg) => writeln!(out, "  {} = {:#010X} ({}) overwrites ${}", name, word, mnemonic, reg)?,
+                Safety::Unsafe(reason) => writeln!(out, "  {} = {:#010X} ({}) is unsafe: {}", name, word, mnemonic, reason)?,
+            }
+        }
     }
+    Ok(())
+}
 
-    state[5] += (data >> (0x20 - (data_last >> 27))) | (data << (data_last >> 27));
-    state[8] = csum(state[8], (data << (0x20 - (data_last >> 27))) | (data >> (data_last >> 27)), loop_count);
+fn scan(opts: ScanOptions) -> Result<(), Error> {
+    let (rom, _) = load_rom(&opts.rom)?;
+    if rom.len() < 4096 {
+        return Err(Error::TooShort { path: opts.rom.clone(), len: rom.len(), expected: 4096 });
+    }
+    report_reachability(&mut std::io::stdout(), &rom)?;
+    Ok(())
+}
 
-    if (loop_count == 0x3F0) return state;
+fn cic_named(name: &str) -> Result<&'static cic::Cic, Error> {
+    cic::by_name(name).ok_or_else(|| Error::Options(format!("unknown CIC {:?}", name)))
+}
 
-    uint tmp1 = csum(state[15], (data >> (0x20 - (data_last >> 27))) | (data << (data_last >> 27)), loop_count);
-    state[15] = csum(tmp1, (data_next << (data >> 27)) | (data_next >> (0x20 - (data >> 27))), loop_count);
+fn seed_for(cic: &Option<String>, seed: u32) -> Result<u32, Error> {
+    match cic {
+        Some(name) => Ok(cic_named(name)?.seed),
+        None => Ok(seed),
+    }
+}
 
-    uint tmp2 = ((data << (0x20 - (data_last & 0x1f))) | (data >> (data_last & 0x1f)));
-    uint tmp3 = csum(state[14], tmp2, loop_count);
-    uint tmp4 = csum(tmp3, (data_next >> (data & 0x1f)) | (data_next << (0x20 - (data & 0x1f))), loop_count);
+fn format_state(buffer: &[u32; 16]) -> String {
+    let words: Vec<String> = buffer.iter().map(|word| format!("{:08X}", word)).collect();
+    words.join(" ")
+}
 
-    state[14] = tmp4;
-    state[13] += ((data >> (data & 0x1f)) | (data << (0x20 - (data & 0x1f)))) + ((data_next >> (data_next & 0x1f)) | (data_next << (0x20 - (data_next & 0x1f))));
-    state[10] = csum(state[10] + data, data_next, loop_count);
-    state[11] = csum(state[11] ^ data, data_next, loop_count);
-    state[12] += (state[8] ^ data);
+fn trace(opts: TraceOptions) -> Result<(), Error> {
+    let seed = seed_for(&opts.cic, opts.seed)?;
+    let rounds = trace::rounds(seed, read_ipl3(&opts.rom)?);
 
-    return state;
+    println!("round data_last data     data_next | state");
+    for round in rounds.iter().filter(|round| round.loop_idx >= opts.from && round.loop_idx <= opts.to) {
+        println!("{:5} {:08X}  {:08X} {:08X}  | {}",
+                 round.loop_idx, round.data_last, round.data, round.data_next, format_state(&round.buffer));
+    }
+    Ok(())
 }
 
-uint[2] finalize(uint[16] state) {
-    uint buf[4];
+fn diff(opts: DiffOptions) -> Result<(), Error> {
+    let seed = seed_for(&opts.cic, opts.seed)?;
+    let a = read_ipl3(&opts.a)?;
+    let b = read_ipl3(&opts.b)?;
+
+    let divergence = match trace::diff(seed, a, b) {
+        Some(divergence) => divergence,
+        None => {
+            println!("The checksum states never diverge");
+            return Ok(());
+        }
+    };
+
+    if let Some(word) = divergence.first_input {
+        println!("First differing word: {} at {:#05X}", word, word * 4);
+    }
+    let words: Vec<String> = divergence.words.iter().map(|word| word.to_string()).collect();
+    println!("States diverge after round {}, in state words {}", divergence.round, words.join(", "));
+
+    let rounds = divergence.round as usize;
+    println!("  {}: {}", opts.a, format_state(&trace::rounds(seed, a)[rounds].buffer));
+    println!("  {}: {}", opts.b, format_state(&trace::rounds(seed, b)[rounds].buffer));
+    Ok(())
+}
 
-    for (int i = 0; i < 4; i++) {
-        buf[i] = state[0];
+fn search(opts: SearchArgs) -> Result<(), Error> {
+    let run_start = Instant::now();
+    let stdout = std::io::stdout();
+    // with --json, stdout carries nothing but events
+    let out = if opts.json {
+        Reporter::with_events(Box::new(std::io::stderr()), Box::new(stdout.lock()))
+    } else {
+        Reporter::new(Box::new(stdout.lock()))
+    };
+    let mut out = out.log_progress(Duration::from_secs(10));
+
+    // catch a bad path before spending hours searching
+    let mut emits = Vec::new();
+    for path in &opts.emit {
+        let format = WordsFormat::from_path(path)
+            .ok_or_else(|| Error::Options(format!("can't tell what to write to {:?}, use a .s, .h or .bin extension", path)))?;
+        emits.push((path, format));
     }
 
-    for (uint i = 0; i < 16; i++) {
-        uint data = state[i];
-        uint shift = data & 0x1f;
-        uint data_shifted_left = data << (32 - shift);
-        uint data_shifted_right = data >> shift;
-        uint tmp = buf[0] + (data_shifted_right | data_shifted_left);
-        buf[0] = tmp;
+    let mut targets = opts.target.clone();
+    let golden = read_ipl3(&opts.golden)?;
+    let (high, low) = ipl3_checksum(opts.seed, golden);
+    targets.push(Target {
+        label: opts.golden.clone(),
+        high,
+        low,
+    });
+
+    let targets = TargetSet::with_mask(targets, opts.match_mask);
+    for target in targets.targets() {
+        writeln!(out, "Target checksum: {}", target)?;
+        out.event(&Event::Target {
+            label: &target.label,
+            high: target.high,
+            low: target.low,
+            mask: targets.mask(),
+        })?;
+    }
+    if targets.mask() != FULL_MASK {
+        writeln!(out, "Match mask: {:#014X}", targets.mask())?;
+    }
 
-        if (data < tmp) {
-            buf[1] += data;
-        }
-        else {
-            buf[1] = csum(buf[1], data, i);
-        }
+    let (mut source, format) = load_rom(&opts.source)?;
+    if let Some(payload) = &opts.payload {
+        let segments = read_payload(&std::fs::read(payload).map_err(Error::io(payload))?).map_err(Error::format(payload))?;
+        source = lay_out(&source, &segments).map_err(Error::format(payload))?;
+        writeln!(out, "Laid out {} into {}", payload, opts.source)?;
+    }
+    let source_rom = ipl3_of(&opts.source, &source)?;
 
-        tmp = (data & 0x02) >> 1;
-        uint tmp2 = data & 0x01;
+    if opts.analyze {
+        let analysis = analysis::analyze(&midstate(opts.seed, source_rom), opts.samples, opts.shards);
+        analysis::print_report(&mut out, &analysis, &targets)?;
+        return Ok(());
+    }
 
-        if (tmp == tmp2) {
-            buf[2] += data;
-        }
-        else {
-            buf[2] = csum(buf[2], data, i);
+    let search_opts = SearchOptions {
+        seed: opts.seed,
+        init: opts.init,
+        deterministic: opts.deterministic,
+        near_miss_mask: opts.near_miss_mask,
+        smt: opts.smt,
+        smt_timeout: opts.smt_timeout,
+        budget: Budget {
+            duration: opts.max_time.map(Duration::from_secs),
+            y_count: opts.max_y,
+            candidates: opts.max_candidates,
+        },
+        ..SearchOptions::default()
+    };
+
+    if (opts.gpu || opts.hybrid) && opts.smt {
+        return Err(Error::Options("--smt is only supported by the CPU backend".to_string()));
+    }
+    let gpu_opts = GpuOptions {
+        threads: opts.threads,
+        groups: opts.groups,
+        verbose: opts.verbose,
+    };
+    let cpu_opts = CpuOptions {
+        workers: opts.workers as usize,
+        pin: opts.pin,
+        background: opts.background,
+        throttle: opts.throttle,
+    };
+
+    let mut ledger = Ledger::new(search_opts.init as u64, search_opts.y_end, search_opts.x_end);
+    let outcome = if opts.hybrid {
+        let mut backends: Vec<Box<dyn Backend>> = vec![Box::new(CpuBackend { cpu: cpu_opts }), Box::new(GpuBackend::new(gpu_opts))];
+        hybrid_search(source_rom, &targets, &search_opts, &mut backends, &mut ledger, &mut out)?
+    } else if opts.gpu {
+        gpu_search(source_rom, &targets, &search_opts, &gpu_opts, &mut out)?
+    } else {
+        cpu_search(source_rom, &targets, &search_opts, &cpu_opts, &mut out)?
+    };
+
+    let Hit { y, x } = match outcome {
+        Outcome::Found(hit) => hit,
+        outcome => {
+            // the first y that wasn't searched in full
+            let resume = if opts.hybrid {
+                ledger.covered_up_to()
+            } else {
+                match out.last_progress() {
+                    Some(at) if at.x >= search_opts.x_end => at.y as u64 + 1,
+                    Some(at) => at.y as u64,
+                    None => search_opts.init as u64,
+                }
+            };
+            let error = match outcome {
+                // stopped after the last y was done, so there's nothing to
+                // resume and no --init that could say so
+                _ if resume >= search_opts.y_end => Error::NotFound,
+                Outcome::BudgetExceeded => Error::BudgetExceeded { resume },
+                Outcome::Cancelled => Error::Cancelled { resume },
+                _ => Error::NotFound,
+            };
+            if let Error::NotFound = error {
+                writeln!(out, "sorry, no dice")?;
+            }
+            out.event(&Event::Finished { found: false, seconds: run_start.elapsed().as_secs_f64() })?;
+            return Err(error);
         }
+    };
+
+    // recompute from scratch rather than trusting the backend's midstate
+    let (high, low) = patched_checksum(opts.seed, source_rom, y, x);
+    writeln!(out, "Result checksum: {:#06X} {:08X}", high, low)?;
+    writeln!(out, "Success found with final two words of {:#X}, {:#X}", y, x)?;
+    let matched = targets.lookup(high, low);
+    if matched.is_empty() {
+        return Err(Error::Backend("verification failed: the patched ROM does not match any target".to_string()));
+    }
+    let labels: Vec<&str> = matched.iter().map(|t| t.label.as_str()).collect();
+    writeln!(out, "Matched target {}", labels.join(", "))?;
+    out.event(&Event::Hit { y, x, high, low, targets: labels })?;
+
+    let mut patched_rom = source_rom;
+    set_free_words(&mut patched_rom, y, x);
+    report_reachability(&mut out, &patched_rom)?;
+
+    let words = Words {
+        hit: Hit { y, x },
+        seed: opts.seed,
+        targets: matched,
+        source_hash: source_hash(&source_rom),
+    };
+    for (path, format) in emits {
+        std::fs::write(path, words.render(format)).map_err(Error::io(path))?;
+        writeln!(out, "Wrote words to {}", path)?;
+    }
 
-        if (tmp2 == 1) {
-            buf[3] ^= data;
+    if let Some(output) = &opts.output {
+        set_free_words(&mut source, y, x);
+        if let Some(format) = format {
+            format.swap(&mut source);
         }
-        else {
-            buf[3] = csum(buf[3], data, i);
+        std::fs::write(output, source).map_err(Error::io(output))?;
+        writeln!(out, "Wrote patched ROM to {}", output)?;
+    }
+    out.event(&Event::Finished { found: true, seconds: run_start.elapsed().as_secs_f64() })?;
+    Ok(())
+}
+
+fn patch(opts: PatchOptions) -> Result<(), Error> {
+    if opts.output.is_none() && opts.ips.is_none() && opts.bps.is_none() {
+        return Err(Error::Options("nothing to write, give --output, --ips or --bps".to_string()));
+    }
+
+    let (original, format) = load_rom(&opts.source)?;
+    if original.len() < 4096 {
+        return Err(Error::TooShort { path: opts.source.clone(), len: original.len(), expected: 4096 });
+    }
+    let mut patched = original.clone();
+    set_free_words(&mut patched, opts.y, opts.x);
+
+    if let Some(name) = &opts.fix_crc {
+        let cic = cic_named(name)?;
+        fix_header_crc(&mut patched, cic.crc);
+        let (crc1, crc2) = stored_crc(&patched);
+        println!("Header CRCs set to {:08X} {:08X} for CIC-NUS-{}", crc1, crc2, cic.name);
+    }
+
+    // patches are always between big-endian images, whatever the source was
+    if let Some(ips) = &opts.ips {
+        let patch = make_ips(&original, &patched).map_err(Error::format(&opts.source))?;
+        std::fs::write(ips, patch).map_err(Error::io(ips))?;
+        println!("Wrote IPS patch to {}", ips);
+    }
+    if let Some(bps) = &opts.bps {
+        std::fs::write(bps, make_bps(&original, &patched)).map_err(Error::io(bps))?;
+        println!("Wrote BPS patch to {}", bps);
+    }
+
+    if let Some(output) = &opts.output {
+        if let Some(format) = format {
+            format.swap(&mut patched);
         }
+        std::fs::write(output, patched).map_err(Error::io(output))?;
+        println!("Wrote patched ROM to {}", output);
     }
+    Ok(())
+}
 
-    uint res[2];
-    res[1] = csum(buf[0], buf[1], 16) & 0xFFFF;
-    res[0] = buf[3] ^ buf[2];
-    return res;
+/// What verify and apply check a ROM against.
+struct Expected<'a> {
+    cic: Option<&'a str>,
+    golden: Option<&'a str>,
+    target: &'a [Target],
+    seed: u32,
 }
 
-uint[2] crunch(uint[16] state_in, uint hi, uint lo) {
-    uint state[16];
-    for (int i = 0; i < 16; i++) {
-        state[i] = state_in[i];
+/// Checks that a big-endian `rom` would boot: its IPL3 checksum must hit an
+/// expected target, and its header CRCs must suit the CIC, if one was given.
+fn check_boots(name: &str, rom: &[u8], format: Option<RomFormat>, expected: Expected) -> Result<(), Error> {
+    let mut seed = expected.seed;
+    let mut targets = expected.target.to_vec();
+    let mut crc_cic = None;
+
+    if let Some(name) = expected.cic {
+        let cic = cic_named(name)?;
+        seed = cic.seed;
+        crc_cic = Some(cic);
+        targets.push(Target {
+            label: format!("CIC-NUS-{}", cic.name),
+            high: (cic.checksum >> 32) as u32,
+            low: cic.checksum as u32,
+        });
     }
 
-    uint data_last = 0;
-    uint data = hi;
-    uint data_next = lo;
-    uint loop_count = 1007;
-
-    state = round(state, data_last, data, data_next, loop_count);
+    if let Some(golden) = expected.golden {
+        let (high, low) = ipl3_checksum(seed, read_ipl3(golden)?);
+        targets.push(Target {
+            label: golden.to_string(),
+            high,
+            low,
+        });
+    }
 
-    data_last = data;
-    data = data_next;
-    data_next = 0;
-    loop_count = 1008;
+    if targets.is_empty() {
+        return Err(Error::Options("nothing to verify against, give --cic, --golden or --target".to_string()));
+    }
 
-    state = round(state, data_last, data, data_next, loop_count);
+    let ipl3 = ipl3_of(name, rom)?;
+    match format {
+        Some(format) => println!("Byte order: {:?}", format),
+        None => println!("Byte order: unknown header {:08X}, assuming big-endian", BigEndian::read_u32(rom)),
+    }
 
-    return finalize(state);
-}
-"#)
-.with_kernel_code(
-r#"
-    uint y = y_offset;
-    uint x = x_offset + gl_GlobalInvocationID.x;
-    uint local_result[2] = crunch(state_in, y, x);
-    if (local_result[1] == target_hi && local_result[0] == target_lo) {
-        if (atomicOr(finished[0], 1) == 0) {
-            result[0] = x;
-            result[1] = y;
+    let mut failures = Vec::new();
+    let targets = TargetSet::new(targets);
+    let (high, low) = ipl3_checksum(seed, ipl3);
+    match targets.lookup(high, low) {
+        [] => {
+            println!("IPL3 checksum: {:#06X} {:08X}, MISMATCH", high, low);
+            for target in targets.targets() {
+                println!("  expected {}", target);
+            }
+            failures.push("the IPL3 checksum doesn't match");
+        }
+        matched => {
+            let labels: Vec<&str> = matched.iter().map(|t| t.label.as_str()).collect();
+            println!("IPL3 checksum: {:#06X} {:08X}, matches {}", high, low, labels.join(", "));
         }
     }
-"#,
-);
-    let c = compile::<GlslKernel, GlslKernelCompile, Vec<u32>, GlobalCache>(kernel)?.finish()?;
-    //return Ok(());
-    let mut finished_src;
-    let unlocked = std::io::stdout();
-    let mut stdout = unlocked.lock();
-    loop {
-        x_off_src = 0;
-        x_off.set(x_off_src as u32)?;
-        let mut y_csum = pre_csum.clone();
-        y_csum.rom[4088] = (y_off_src >> 24) as u8;
-        y_csum.rom[4089] = (y_off_src >> 16) as u8;
-        y_csum.rom[4090] = (y_off_src >> 8) as u8;
-        y_csum.rom[4091] = (y_off_src >> 0) as u8;
-        y_csum.checksum(1005, 1006);
-        let state_vec: Vec<u32> = y_csum.buffer.iter().cloned().collect();
-        let state_in: DeviceBox<[u32]> = state_vec.as_device_boxed()?;
-        let start = Instant::now();
-        loop {
-            let bump = (opts.threads as u64) * (opts.groups as u64);
-            if opts.verbose {
-                stdout.write_fmt(format_args!(
-                    "should calc from ({}, {}) to ({}, {}) in {} threads on {} workgroups\n",
-                    x_off_src,
-                    y_off_src,
-                    x_off_src + bump,
-                    y_off_src,
-                    opts.threads,
-                    opts.groups
-                ))?;
-            }
-            unsafe {
-                spawn(opts.groups).launch(call!(
-                    c.clone(),
-                    &state_in,
-                    &mut x_off,
-                    &mut y_off,
-                    &mut finished,
-                    &mut res
-                ))?;
-            }
-            finished_src = futures::executor::block_on(finished.get())?[0] == 1;
-            if finished_src {
-                break;
-            }
-
-            x_off_src += bump;
 
-            if x_off_src >= std::u32::MAX as u64 {
-                break;
+    match crc_cic {
+        Some(cic) => {
+            if rom.len() < CRC_START + CRC_LENGTH {
+                println!("Note: the ROM ends before {:#X}, the CRCs are computed as if it were padded with zeroes",
+                         CRC_START + CRC_LENGTH);
             }
 
-            x_off.set(x_off_src as u32)?;
+            let expected = header_crc(rom, cic.crc);
+            let stored = stored_crc(rom);
+            for (name, stored, expected) in [("CRC1", stored.0, expected.0), ("CRC2", stored.1, expected.1)].iter() {
+                if stored == expected {
+                    println!("{}: {:08X}, ok", name, stored);
+                } else {
+                    println!("{}: {:08X}, MISMATCH, CIC-NUS-{} expects {:08X}", name, stored, cic.name, expected);
+                    failures.push("a header CRC doesn't match");
+                }
+            }
         }
-        let duration = start.elapsed();
-        write!(stdout, "Inner loop Y=={} took {:?}\n", y_off_src, duration)?;
-        stdout.flush()?;
-        //return Ok(());
+        None => println!("Header CRCs not checked, give --cic to check them"),
+    }
 
-        if finished_src {
-            break;
-        }
+    if failures.is_empty() {
+        println!("{} should boot", name);
+        Ok(())
+    } else {
+        failures.dedup();
+        Err(Error::Check(format!("{} won't boot: {}", name, failures.join(", "))))
+    }
+}
 
-        y_off_src += 1;
+fn verify(opts: VerifyOptions) -> Result<(), Error> {
+    let (rom, format) = load_rom(&opts.rom)?;
+    check_boots(&opts.rom, &rom, format, Expected {
+        cic: opts.cic.as_deref(),
+        golden: opts.golden.as_deref(),
+        target: &opts.target,
+        seed: opts.seed,
+    })
+}
 
-        if y_off_src >= std::u32::MAX as u64 {
-            break;
+fn apply(opts: ApplyOptions) -> Result<(), Error> {
+    let (rom, format) = load_rom(&opts.rom)?;
+    let patch = std::fs::read(&opts.patch).map_err(Error::io(&opts.patch))?;
+    let mut patched = apply_patch(&rom, &patch).map_err(Error::format(&opts.patch))?;
+
+    let name = opts.output.as_deref().unwrap_or("the patched ROM");
+    check_boots(name, &patched, format, Expected {
+        cic: opts.cic.as_deref(),
+        golden: opts.golden.as_deref(),
+        target: &opts.target,
+        seed: opts.seed,
+    })?;
+
+    if let Some(output) = &opts.output {
+        if let Some(format) = format {
+            format.swap(&mut patched);
         }
-
-        y_off.set(y_off_src as u32)?;
+        std::fs::write(output, patched).map_err(Error::io(output))?;
+        println!("Wrote patched ROM to {}", output);
     }
+    Ok(())
+}
 
-    // download from GPU and print out
-    if finished_src {
-        println!("{:#X?}", futures::executor::block_on(res.get())?);
+fn bench(opts: BenchOptions) -> Result<(), Error> {
+    let mut rng = rand::thread_rng();
+    let source_rom = match &opts.source {
+        Some(path) => read_ipl3(path)?,
+        None => {
+            let mut rom = [0u8; 4096];
+            rng.fill(&mut rom[..]);
+            rom
+        }
+    };
+
+    // a random target, so a hit is as unlikely as it is in a real search
+    let targets = TargetSet::new(vec![Target {
+        label: "bench".to_string(),
+        high: rng.gen::<u32>() & 0xFFFF,
+        low: rng.gen(),
+    }]);
+
+    let search_opts = SearchOptions {
+        seed: opts.seed,
+        init: 0,
+        y_end: 1,
+        x_end: opts.candidates.min(1 << 32),
+        ..SearchOptions::default()
+    };
+
+    let start = Instant::now();
+    if opts.gpu {
+        let gpu_opts = GpuOptions {
+            threads: opts.threads,
+            groups: opts.groups,
+            verbose: false,
+        };
+        gpu_search(source_rom, &targets, &search_opts, &gpu_opts, &mut Reporter::sink())?;
     } else {
-        println!("sorry, no dice");
+        let cpu_opts = CpuOptions {
+            workers: opts.workers as usize,
+            ..CpuOptions::default()
+        };
+        cpu_search(source_rom, &targets, &search_opts, &cpu_opts, &mut Reporter::sink())?;
     }
+    let duration = start.elapsed();
+    let tried = search_opts.x_end;
+
+    println!("{} candidates in {:?}: {:.2} MH/s",
+             tried, duration, tried as f64 / duration.as_secs_f64() / 1e6);
     Ok(())
 }
+
+fn main() {
+    let args = Args::parse_args_default_or_exit();
+
+    let result = match args.command {
+        Some(Command::Hash(opts)) => hash(opts),
+        Some(Command::Detect(opts)) => detect(opts),
+        Some(Command::Scan(opts)) => scan(opts),
+        Some(Command::Trace(opts)) => trace(opts),
+        Some(Command::Diff(opts)) => diff(opts),
+        Some(Command::Search(opts)) => search(opts),
+        Some(Command::Patch(opts)) => patch(opts),
+        Some(Command::Verify(opts)) => verify(opts),
+        Some(Command::Apply(opts)) => apply(opts),
+        Some(Command::Bench(opts)) => bench(opts),
+        None => {
+            eprintln!("{}", Args::usage());
+            eprintln!();
+            eprintln!("Available commands:");
+            eprintln!("{}", Args::command_list().unwrap_or(""));
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
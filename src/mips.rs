@@ -0,0 +1,325 @@
+// Just enough of a VR4300 decoder to follow IPL3's control flow, see which
+// addresses it loads and stores, and judge whether a word is harmless to run.
+
+pub const RA: u8 = 31;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    Next,
+    /// A conditional branch. The delay slot runs before it's taken.
+    Branch { target: u32, link: bool },
+    Jump { target: u32, link: bool },
+    JumpRegister { reg: u8, link: bool },
+    /// eret, or an instruction that always raises an exception.
+    Stop,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AluOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Nor,
+    Sll,
+    Srl,
+    Sra,
+}
+
+impl AluOp {
+    pub fn apply(self, a: u32, b: u32) -> u32 {
+        match self {
+            AluOp::Add => a.wrapping_add(b),
+            AluOp::Sub => a.wrapping_sub(b),
+            AluOp::And => a & b,
+            AluOp::Or => a | b,
+            AluOp::Xor => a ^ b,
+            AluOp::Nor => !(a | b),
+            AluOp::Sll => a.wrapping_shl(b),
+            AluOp::Srl => a.wrapping_shr(b),
+            AluOp::Sra => (a as i32).wrapping_shr(b) as u32,
+        }
+    }
+}
+
+/// What an instruction writes to its destination GPR, as far as tracking
+/// constants needs to know. Only the low 32 bits are tracked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Const(u32),
+    RegImm(AluOp, u8, u32),
+    RegReg(AluOp, u8, u8),
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mem {
+    pub store: bool,
+    pub base: u8,
+    pub offset: i32,
+    pub size: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Insn {
+    pub mnemonic: &'static str,
+    pub flow: Flow,
+    pub mem: Option<Mem>,
+    pub dest: Option<(u8, Value)>,
+    /// Whether HI and LO are overwritten, which matters when the code around
+    /// reads them back with mfhi or mflo.
+    pub writes_hi_lo: bool,
+    /// Why running this could do something drastic, such as raising an
+    /// exception or reconfiguring the CPU.
+    pub hazard: Option<&'static str>,
+}
+
+impl Insn {
+    fn new(mnemonic: &'static str) -> Insn {
+        Insn {
+            mnemonic,
+            flow: Flow::Next,
+            mem: None,
+            dest: None,
+            writes_hi_lo: false,
+            hazard: None,
+        }
+    }
+
+    fn reserved() -> Insn {
+        Insn {
+            flow: Flow::Stop,
+            hazard: Some("reserved instruction"),
+            ..Insn::new("(reserved)")
+        }
+    }
+
+    fn writes(mut self, reg: u8, value: Value) -> Insn {
+        self.dest = Some((reg, value));
+        self
+    }
+
+    fn writes_hi_lo(mut self) -> Insn {
+        self.writes_hi_lo = true;
+        self
+    }
+
+    fn hazard(mut self, reason: &'static str) -> Insn {
+        self.hazard = Some(reason);
+        self
+    }
+}
+
+fn mem(mnemonic: &'static str, word: u32, store: bool, size: u32) -> Insn {
+    let insn = Insn {
+        mem: Some(Mem {
+            store,
+            base: ((word >> 21) & 0x1F) as u8,
+            offset: word as i16 as i32,
+            size,
+        }),
+        ..Insn::new(mnemonic)
+    };
+    if store {
+        insn
+    } else {
+        insn.writes(((word >> 16) & 0x1F) as u8, Value::Unknown)
+    }
+}
+
+pub fn decode(word: u32, pc: u32) -> Insn {
+    let rs = ((word >> 21) & 0x1F) as u8;
+    let rt = ((word >> 16) & 0x1F) as u8;
+    let rd = ((word >> 11) & 0x1F) as u8;
+    let sa = (word >> 6) & 0x1F;
+    let imm = word & 0xFFFF;
+    let simm = word as i16 as i32 as u32;
+    let branch = pc.wrapping_add(4).wrapping_add(simm << 2);
+    let jump = (pc.wrapping_add(4) & 0xF000_0000) | ((word & 0x03FF_FFFF) << 2);
+
+    // linking instructions return past their delay slot
+    let ret = Value::Const(pc.wrapping_add(8));
+    let b = |mnemonic, link: bool| {
+        let insn = Insn { flow: Flow::Branch { target: branch, link }, ..Insn::new(mnemonic) };
+        if link { insn.writes(RA, ret) } else { insn }
+    };
+
+    match word >> 26 {
+        0x00 => match word & 0x3F {
+            0x00 if word == 0 => Insn::new("nop"),
+            0x00 => Insn::new("sll").writes(rd, Value::RegImm(AluOp::Sll, rt, sa)),
+            0x02 => Insn::new("srl").writes(rd, Value::RegImm(AluOp::Srl, rt, sa)),
+            0x03 => Insn::new("sra").writes(rd, Value::RegImm(AluOp::Sra, rt, sa)),
+            0x04 => Insn::new("sllv").writes(rd, Value::Unknown),
+            0x06 => Insn::new("srlv").writes(rd, Value::Unknown),
+            0x07 => Insn::new("srav").writes(rd, Value::Unknown),
+            0x08 => Insn { flow: Flow::JumpRegister { reg: rs, link: false }, ..Insn::new("jr") },
+            0x09 => Insn { flow: Flow::JumpRegister { reg: rs, link: true }, ..Insn::new("jalr") }
+                .writes(rd, ret),
+            0x0C => Insn { flow: Flow::Stop, ..Insn::new("syscall") }.hazard("system call"),
+            0x0D => Insn { flow: Flow::Stop, ..Insn::new("break") }.hazard("breakpoint"),
+            0x0F => Insn::new("sync"),
+            0x10 => Insn::new("mfhi").writes(rd, Value::Unknown),
+            0x11 => Insn::new("mthi").writes_hi_lo(),
+            0x12 => Insn::new("mflo").writes(rd, Value::Unknown),
+            0x13 => Insn::new("mtlo").writes_hi_lo(),
+            0x14 => Insn::new("dsllv").writes(rd, Value::Unknown),
+            0x16 => Insn::new("dsrlv").writes(rd, Value::Unknown),
+            0x17 => Insn::new("dsrav").writes(rd, Value::Unknown),
+            0x18 => Insn::new("mult").writes_hi_lo(),
+            0x19 => Insn::new("multu").writes_hi_lo(),
+            0x1A => Insn::new("div").writes_hi_lo(),
+            0x1B => Insn::new("divu").writes_hi_lo(),
+            0x1C => Insn::new("dmult").writes_hi_lo(),
+            0x1D => Insn::new("dmultu").writes_hi_lo(),
+            0x1E => Insn::new("ddiv").writes_hi_lo(),
+            0x1F => Insn::new("ddivu").writes_hi_lo(),
+            0x20 => Insn::new("add").writes(rd, Value::RegReg(AluOp::Add, rs, rt)).hazard("traps on overflow"),
+            0x21 => Insn::new("addu").writes(rd, Value::RegReg(AluOp::Add, rs, rt)),
+            0x22 => Insn::new("sub").writes(rd, Value::RegReg(AluOp::Sub, rs, rt)).hazard("traps on overflow"),
+            0x23 => Insn::new("subu").writes(rd, Value::RegReg(AluOp::Sub, rs, rt)),
+            0x24 => Insn::new("and").writes(rd, Value::RegReg(AluOp::And, rs, rt)),
+            0x25 => Insn::new("or").writes(rd, Value::RegReg(AluOp::Or, rs, rt)),
+            0x26 => Insn::new("xor").writes(rd, Value::RegReg(AluOp::Xor, rs, rt)),
+            0x27 => Insn::new("nor").writes(rd, Value::RegReg(AluOp::Nor, rs, rt)),
+            0x2A => Insn::new("slt").writes(rd, Value::Unknown),
+            0x2B => Insn::new("sltu").writes(rd, Value::Unknown),
+            0x2C => Insn::new("dadd").writes(rd, Value::RegReg(AluOp::Add, rs, rt)).hazard("traps on overflow"),
+            0x2D => Insn::new("daddu").writes(rd, Value::RegReg(AluOp::Add, rs, rt)),
+            0x2E => Insn::new("dsub").writes(rd, Value::RegReg(AluOp::Sub, rs, rt)).hazard("traps on overflow"),
+            0x2F => Insn::new("dsubu").writes(rd, Value::RegReg(AluOp::Sub, rs, rt)),
+            0x30 => Insn::new("tge").hazard("conditional trap"),
+            0x31 => Insn::new("tgeu").hazard("conditional trap"),
+            0x32 => Insn::new("tlt").hazard("conditional trap"),
+            0x33 => Insn::new("tltu").hazard("conditional trap"),
+            0x34 => Insn::new("teq").hazard("conditional trap"),
+            0x36 => Insn::new("tne").hazard("conditional trap"),
+            0x38 => Insn::new("dsll").writes(rd, Value::Unknown),
+            0x3A => Insn::new("dsrl").writes(rd, Value::Unknown),
+            0x3B => Insn::new("dsra").writes(rd, Value::Unknown),
+            0x3C => Insn::new("dsll32").writes(rd, Value::Unknown),
+            0x3E => Insn::new("dsrl32").writes(rd, Value::Unknown),
+            0x3F => Insn::new("dsra32").writes(rd, Value::Unknown),
+            _ => Insn::reserved(),
+        },
+        0x01 => match rt {
+            0x00 => b("bltz", false),
+            0x01 => b("bgez", false),
+            0x02 => b("bltzl", false),
+            0x03 => b("bgezl", false),
+            0x08 => Insn::new("tgei").hazard("conditional trap"),
+            0x09 => Insn::new("tgeiu").hazard("conditional trap"),
+            0x0A => Insn::new("tlti").hazard("conditional trap"),
+            0x0B => Insn::new("tltiu").hazard("conditional trap"),
+            0x0C => Insn::new("teqi").hazard("conditional trap"),
+            0x0E => Insn::new("tnei").hazard("conditional trap"),
+            0x10 => b("bltzal", true),
+            0x11 => b("bgezal", true),
+            0x12 => b("bltzall", true),
+            0x13 => b("bgezall", true),
+            _ => Insn::reserved(),
+        },
+        0x02 => Insn { flow: Flow::Jump { target: jump, link: false }, ..Insn::new("j") },
+        0x03 => Insn { flow: Flow::Jump { target: jump, link: true }, ..Insn::new("jal") }.writes(RA, ret),
+        0x04 => b("beq", false),
+        0x05 => b("bne", false),
+        0x06 => b("blez", false),
+        0x07 => b("bgtz", false),
+        0x08 => Insn::new("addi").writes(rt, Value::RegImm(AluOp::Add, rs, simm)).hazard("traps on overflow"),
+        0x09 => Insn::new("addiu").writes(rt, Value::RegImm(AluOp::Add, rs, simm)),
+        0x0A => Insn::new("slti").writes(rt, Value::Unknown),
+        0x0B => Insn::new("sltiu").writes(rt, Value::Unknown),
+        0x0C => Insn::new("andi").writes(rt, Value::RegImm(AluOp::And, rs, imm)),
+        0x0D => Insn::new("ori").writes(rt, Value::RegImm(AluOp::Or, rs, imm)),
+        0x0E => Insn::new("xori").writes(rt, Value::RegImm(AluOp::Xor, rs, imm)),
+        0x0F => Insn::new("lui").writes(rt, Value::Const(imm << 16)),
+        0x10 => match rs {
+            0x00 | 0x01 => Insn::new("mfc0").writes(rt, Value::Unknown),
+            0x04 | 0x05 => Insn::new("mtc0").hazard("writes a coprocessor 0 register"),
+            // with the CO bit set, the function field picks the operation
+            0x10..=0x1F => match word & 0x3F {
+                0x01 => Insn::new("tlbr").hazard("changes coprocessor 0 registers"),
+                0x02 => Insn::new("tlbwi").hazard("changes the TLB"),
+                0x06 => Insn::new("tlbwr").hazard("changes the TLB"),
+                0x08 => Insn::new("tlbp").hazard("changes coprocessor 0 registers"),
+                0x18 => Insn { flow: Flow::Stop, ..Insn::new("eret") }.hazard("returns from exception"),
+                _ => Insn::reserved(),
+            },
+            _ => Insn::reserved(),
+        },
+        0x11 => match rs {
+            0x00..=0x02 => Insn::new("mfc1").writes(rt, Value::Unknown).hazard("needs the FPU enabled"),
+            0x08 => b("bc1", false).hazard("needs the FPU enabled"),
+            _ => Insn::new("cop1").hazard("needs the FPU enabled"),
+        },
+        0x12 => Insn::new("cop2").hazard("coprocessor 2 is unusable"),
+        0x14 => b("beql", false),
+        0x15 => b("bnel", false),
+        0x16 => b("blezl", false),
+        0x17 => b("bgtzl", false),
+        0x18 => Insn::new("daddi").writes(rt, Value::RegImm(AluOp::Add, rs, simm)).hazard("traps on overflow"),
+        0x19 => Insn::new("daddiu").writes(rt, Value::RegImm(AluOp::Add, rs, simm)),
+        0x1A => mem("ldl", word, false, 8),
+        0x1B => mem("ldr", word, false, 8),
+        0x20 => mem("lb", word, false, 1),
+        0x21 => mem("lh", word, false, 2),
+        0x22 => mem("lwl", word, false, 4),
+        0x23 => mem("lw", word, false, 4),
+        0x24 => mem("lbu", word, false, 1),
+        0x25 => mem("lhu", word, false, 2),
+        0x26 => mem("lwr", word, false, 4),
+        0x27 => mem("lwu", word, false, 4),
+        0x28 => mem("sb", word, true, 1),
+        0x29 => mem("sh", word, true, 2),
+        0x2A => mem("swl", word, true, 4),
+        0x2B => mem("sw", word, true, 4),
+        0x2C => mem("sdl", word, true, 8),
+        0x2D => mem("sdr", word, true, 8),
+        0x2E => mem("swr", word, true, 4),
+        0x2F => Insn::new("cache").hazard("cache operation"),
+        0x30 => mem("ll", word, false, 4),
+        0x34 => mem("lld", word, false, 8),
+        0x37 => mem("ld", word, false, 8),
+        0x38 => mem("sc", word, true, 4).writes(rt, Value::Unknown),
+        0x3C => mem("scd", word, true, 8).writes(rt, Value::Unknown),
+        0x3F => mem("sd", word, true, 8),
+        // FPU loads write FPRs, not GPRs
+        0x31 => Insn { dest: None, ..mem("lwc1", word, false, 4) }.hazard("needs the FPU enabled"),
+        0x35 => Insn { dest: None, ..mem("ldc1", word, false, 8) }.hazard("needs the FPU enabled"),
+        0x39 => mem("swc1", word, true, 4).hazard("needs the FPU enabled"),
+        0x3D => mem("sdc1", word, true, 8).hazard("needs the FPU enabled"),
+        _ => Insn::reserved(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Safety {
+    /// Runs without any architectural effect.
+    NoEffect,
+    /// Runs without trapping or branching, but changes a register.
+    WritesRegister(u8),
+    Unsafe(&'static str),
+}
+
+/// Judges what happens if `word` is executed at `pc` in the middle of some
+/// other code.
+pub fn safety(word: u32, pc: u32) -> Safety {
+    let insn = decode(word, pc);
+    if let Some(reason) = insn.hazard {
+        return Safety::Unsafe(reason);
+    }
+    if insn.flow != Flow::Next {
+        return Safety::Unsafe("changes control flow");
+    }
+    if insn.mem.is_some() {
+        return Safety::Unsafe("accesses memory");
+    }
+    if insn.writes_hi_lo {
+        return Safety::Unsafe("overwrites HI/LO");
+    }
+    match insn.dest {
+        Some((reg, _)) if reg != 0 => Safety::WritesRegister(reg),
+        _ => Safety::NoEffect,
+    }
+}
@@ -0,0 +1,266 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+
+// IPS and BPS patches for distributing an IPL3 replacement without the ROM it
+// was made for. Both are made and applied against big-endian images.
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const IPS_MAX_RECORD: usize = 0xFFFF;
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Spans of `patched` that differ from `original`, including anything past the
+/// end of `original`.
+fn changed_runs(original: &[u8], patched: &[u8]) -> Vec<(usize, usize)> {
+    let differs = |i: usize| original.get(i) != Some(&patched[i]);
+
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < patched.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < patched.len() && differs(i) {
+            i += 1;
+        }
+        runs.push((start, i));
+    }
+    runs
+}
+
+pub fn make_ips(original: &[u8], patched: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut out = IPS_MAGIC.to_vec();
+
+    for (start, end) in changed_runs(original, patched) {
+        if end > 1 << 24 {
+            return Err(invalid("IPS can't patch past 16 MiB, use BPS instead"));
+        }
+
+        let mut chunk_start = start;
+        while chunk_start < end {
+            // a record at 0x454F46 would read as the end marker, so start it
+            // one byte early and carry the byte before along
+            if chunk_start == 0x45_4F46 {
+                chunk_start -= 1;
+            }
+            let chunk_end = (chunk_start + IPS_MAX_RECORD).min(end);
+            out.extend_from_slice(&(chunk_start as u32).to_be_bytes()[1..]);
+            out.extend_from_slice(&((chunk_end - chunk_start) as u16).to_be_bytes());
+            out.extend_from_slice(&patched[chunk_start..chunk_end]);
+            chunk_start = chunk_end;
+        }
+    }
+
+    out.extend_from_slice(IPS_EOF);
+    if patched.len() < original.len() {
+        out.extend_from_slice(&(patched.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(out)
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> std::io::Result<Vec<u8>> {
+    if !patch.starts_with(IPS_MAGIC) {
+        return Err(invalid("not an IPS patch"));
+    }
+
+    let mut out = rom.to_vec();
+    let mut pos = IPS_MAGIC.len();
+    let take = |pos: &mut usize, n: usize| -> std::io::Result<&[u8]> {
+        let bytes = patch.get(*pos..*pos + n).ok_or_else(|| invalid("IPS patch is truncated"))?;
+        *pos += n;
+        Ok(bytes)
+    };
+
+    loop {
+        let offset = take(&mut pos, 3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let size = take(&mut pos, 2)?;
+        let size = u16::from_be_bytes([size[0], size[1]]) as usize;
+
+        let data = if size == 0 {
+            // run-length record: a 16-bit count and the byte to repeat
+            let rle = take(&mut pos, 3)?;
+            vec![rle[2]; u16::from_be_bytes([rle[0], rle[1]]) as usize]
+        } else {
+            take(&mut pos, size)?.to_vec()
+        };
+
+        if out.len() < offset + data.len() {
+            out.resize(offset + data.len(), 0);
+        }
+        out[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    // an optional truncation length follows the end marker
+    if let Some(length) = patch.get(pos..pos + 3) {
+        out.truncate(u32::from_be_bytes([0, length[0], length[1], length[2]]) as usize);
+    }
+    Ok(out)
+}
+
+fn write_bps_number(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let low = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(0x80 | low);
+            break;
+        }
+        out.push(low);
+        n -= 1;
+    }
+}
+
+fn read_bps_number(patch: &[u8], pos: &mut usize) -> std::io::Result<u64> {
+    let mut n = 0u64;
+    let mut shift = 1u64;
+    loop {
+        let byte = *patch.get(*pos).ok_or_else(|| invalid("BPS patch is truncated"))?;
+        *pos += 1;
+        n = ((byte & 0x7F) as u64).checked_mul(shift).and_then(|digit| n.checked_add(digit))
+            .ok_or_else(|| invalid("BPS number overflows"))?;
+        if byte & 0x80 != 0 {
+            return Ok(n);
+        }
+        shift = shift.checked_mul(0x80).ok_or_else(|| invalid("BPS number overflows"))?;
+        n = n.checked_add(shift).ok_or_else(|| invalid("BPS number overflows"))?;
+    }
+}
+
+/// A BPS number that must fit in memory, such as a size.
+fn read_bps_size(patch: &[u8], pos: &mut usize) -> std::io::Result<usize> {
+    usize::try_from(read_bps_number(patch, pos)?).map_err(|_| invalid("BPS size is too large"))
+}
+
+const BPS_SOURCE_READ: u64 = 0;
+const BPS_TARGET_READ: u64 = 1;
+const BPS_SOURCE_COPY: u64 = 2;
+const BPS_TARGET_COPY: u64 = 3;
+
+/// Makes a BPS patch from unchanged runs read from the source and changed runs
+/// stored inline, which is all an IPL3 swap needs.
+pub fn make_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = BPS_MAGIC.to_vec();
+    write_bps_number(&mut out, source.len() as u64);
+    write_bps_number(&mut out, target.len() as u64);
+    write_bps_number(&mut out, 0);
+
+    let mut next = 0;
+    for (start, end) in changed_runs(source, target).into_iter().chain(std::iter::once((target.len(), target.len()))) {
+        if start > next {
+            write_bps_number(&mut out, ((start - next - 1) as u64) << 2 | BPS_SOURCE_READ);
+        }
+        if end > start {
+            write_bps_number(&mut out, ((end - start - 1) as u64) << 2 | BPS_TARGET_READ);
+            out.extend_from_slice(&target[start..end]);
+        }
+        next = end;
+    }
+
+    out.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    out.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let patch_crc = crc32fast::hash(&out);
+    out.extend_from_slice(&patch_crc.to_le_bytes());
+    out
+}
+
+fn read_le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> std::io::Result<Vec<u8>> {
+    if !patch.starts_with(BPS_MAGIC) || patch.len() < BPS_MAGIC.len() + 12 {
+        return Err(invalid("not a BPS patch"));
+    }
+    let footer = patch.len() - 12;
+    if crc32fast::hash(&patch[..footer + 8]) != read_le_u32(&patch[footer + 8..]) {
+        return Err(invalid("BPS patch is corrupt"));
+    }
+    if crc32fast::hash(source) != read_le_u32(&patch[footer..]) {
+        return Err(invalid("BPS patch was made for a different ROM"));
+    }
+
+    let mut pos = BPS_MAGIC.len();
+    let source_size = read_bps_size(patch, &mut pos)?;
+    let target_size = read_bps_size(patch, &mut pos)?;
+    let metadata_size = read_bps_size(patch, &mut pos)?;
+    pos = pos.checked_add(metadata_size).ok_or_else(|| invalid("BPS patch is truncated"))?;
+    if source_size != source.len() {
+        return Err(invalid("BPS patch was made for a different ROM"));
+    }
+
+    // every byte comes from the source or the patch, at least until target
+    // copies start repeating them, so don't trust a bigger size up front
+    let mut target = Vec::with_capacity(target_size.min(source.len() + patch.len()));
+    let mut source_rel = 0i64;
+    let mut target_rel = 0i64;
+    while pos < footer {
+        let action = read_bps_number(patch, &mut pos)?;
+        let length = usize::try_from(action >> 2).ok().and_then(|n| n.checked_add(1))
+            .ok_or_else(|| invalid("BPS action is too long"))?;
+        if target.len().checked_add(length).is_none_or(|end| end > target_size) {
+            return Err(invalid("BPS patch writes past the end of the target"));
+        }
+        match action & 3 {
+            BPS_SOURCE_READ => {
+                let start = target.len();
+                let bytes = start.checked_add(length).and_then(|end| source.get(start..end))
+                    .ok_or_else(|| invalid("BPS read past the source"))?;
+                target.extend_from_slice(bytes);
+            }
+            BPS_TARGET_READ => {
+                let bytes = pos.checked_add(length).filter(|&end| end <= footer).map(|end| &patch[pos..end])
+                    .ok_or_else(|| invalid("BPS patch is truncated"))?;
+                target.extend_from_slice(bytes);
+                pos += length;
+            }
+            BPS_SOURCE_COPY | BPS_TARGET_COPY => {
+                let data = read_bps_number(patch, &mut pos)?;
+                let delta = if data & 1 == 1 { -((data >> 1) as i64) } else { (data >> 1) as i64 };
+                if action & 3 == BPS_SOURCE_COPY {
+                    source_rel = source_rel.checked_add(delta).ok_or_else(|| invalid("BPS copy past the source"))?;
+                    let start = usize::try_from(source_rel).map_err(|_| invalid("BPS copy before the source"))?;
+                    let bytes = start.checked_add(length).and_then(|end| source.get(start..end))
+                        .ok_or_else(|| invalid("BPS copy past the source"))?;
+                    target.extend_from_slice(bytes);
+                    source_rel += length as i64;
+                } else {
+                    target_rel = target_rel.checked_add(delta).ok_or_else(|| invalid("BPS copy outside the target"))?;
+                    // the copy may overlap what it's writing, so go byte by byte
+                    for _ in 0..length {
+                        let byte = usize::try_from(target_rel).ok().and_then(|i| target.get(i).copied())
+                            .ok_or_else(|| invalid("BPS copy outside the target"))?;
+                        target.push(byte);
+                        target_rel += 1;
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if target.len() != target_size || crc32fast::hash(&target) != read_le_u32(&patch[footer + 4..]) {
+        return Err(invalid("BPS patch produced the wrong ROM"));
+    }
+    Ok(target)
+}
+
+/// Applies an IPS or BPS patch, whichever `patch` turns out to be.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> std::io::Result<Vec<u8>> {
+    if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else {
+        Err(invalid("not an IPS or BPS patch"))
+    }
+}
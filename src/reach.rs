@@ -0,0 +1,211 @@
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::mips::{decode, Flow, Insn, Value, RA};
+
+// A conservative scan of IPL3 for anything that could execute, load or store
+// the free words. Register contents are tracked as 32-bit constants where they
+// can be worked out, which is enough to follow the lui/addiu pairs IPL3 builds
+// its pointers and jump targets from. Whatever can't be worked out is counted,
+// so a clean result can be told apart from an uninformed one.
+
+/// IPL2 jumps to IPL3 in SP DMEM through KSEG1.
+pub const ENTRY: u32 = 0xA400_0040;
+pub const FREE_WORDS: usize = 4088;
+const SP_MEMORY: u32 = 0x0400_0000;
+
+type Regs = [Option<u32>; 32];
+
+/// How many $ra values a pc is scanned with before $ra is taken as unknown
+/// there, so a loop that does arithmetic on $ra still converges.
+const RA_CONTEXTS: usize = 8;
+
+pub struct Reachability {
+    /// ROM offsets of the instructions that may run.
+    pub executed: BTreeSet<usize>,
+    /// ROM offsets and mnemonics of the loads and stores that may touch the
+    /// free words.
+    pub references: BTreeSet<(usize, &'static str)>,
+    /// ROM offsets of jumps through registers that couldn't be worked out.
+    pub unresolved_jumps: BTreeSet<usize>,
+    /// ROM offsets of loads and stores through pointers that couldn't be
+    /// worked out.
+    pub unresolved_accesses: BTreeSet<usize>,
+    /// Where control leaves DMEM.
+    pub exits: BTreeSet<u32>,
+}
+
+impl Reachability {
+    pub fn free_words_executed(&self) -> bool {
+        self.executed.contains(&FREE_WORDS) || self.executed.contains(&(FREE_WORDS + 4))
+    }
+
+    pub fn free_words_reachable(&self) -> bool {
+        self.free_words_executed() || !self.references.is_empty()
+    }
+}
+
+/// Maps an address to a DMEM offset, following the mirrors of SP memory.
+fn dmem_offset(addr: u32) -> Option<usize> {
+    let physical = addr & 0x1FFF_FFFF;
+    if !(SP_MEMORY..SP_MEMORY + 0x4_0000).contains(&physical) || physical & 0x1000 != 0 {
+        return None;
+    }
+    Some((physical & 0xFFF) as usize)
+}
+
+fn code_offset(pc: u32) -> Option<usize> {
+    dmem_offset(pc).filter(|&offset| offset >= 0x40 && pc & 3 == 0)
+}
+
+fn eval(value: Value, regs: &Regs) -> Option<u32> {
+    match value {
+        Value::Const(c) => Some(c),
+        Value::RegImm(op, rs, imm) => regs[rs as usize].map(|a| op.apply(a, imm)),
+        Value::RegReg(op, rs, rt) => Some(op.apply(regs[rs as usize]?, regs[rt as usize]?)),
+        Value::Unknown => None,
+    }
+}
+
+struct Scanner<'a> {
+    rom: &'a [u8],
+    reach: Reachability,
+    // keyed by $ra as well, so a function called from several places
+    // returns to each of them instead of to an unknown address
+    states: BTreeMap<(u32, Option<u32>), Regs>,
+    queue: VecDeque<(u32, Option<u32>)>,
+}
+
+impl<'a> Scanner<'a> {
+    fn propagate(&mut self, pc: u32, mut regs: Regs) {
+        let mut key = (pc, regs[RA as usize]);
+        if key.1.is_some()
+            && !self.states.contains_key(&key)
+            && self.states.range((pc, None)..=(pc, Some(u32::MAX))).count() >= RA_CONTEXTS
+        {
+            regs[RA as usize] = None;
+            key.1 = None;
+        }
+        let merged = match self.states.get(&key) {
+            Some(old) => {
+                let mut merged = *old;
+                for (m, new) in merged.iter_mut().zip(regs.iter()) {
+                    if m != new {
+                        *m = None;
+                    }
+                }
+                if merged == *old {
+                    return;
+                }
+                merged
+            }
+            None => regs,
+        };
+        self.states.insert(key, merged);
+        self.queue.push_back(key);
+    }
+
+    /// Runs the instruction at `pc` against `regs`, recording what it touches,
+    /// and returns the instruction if there is one to run.
+    fn step(&mut self, pc: u32, regs: &mut Regs) -> Option<Insn> {
+        let offset = match code_offset(pc) {
+            Some(offset) => offset,
+            None => {
+                self.reach.exits.insert(pc);
+                return None;
+            }
+        };
+        self.reach.executed.insert(offset);
+
+        let insn = decode(BigEndian::read_u32(&self.rom[offset..]), pc);
+        if let Some(mem) = insn.mem {
+            match regs[mem.base as usize] {
+                Some(base) => {
+                    let addr = base.wrapping_add(mem.offset as u32);
+                    let touches = (0..mem.size).any(|i| {
+                        // unaligned lwl/lwr and friends touch the enclosing word
+                        let byte = (addr & !(mem.size - 1)).wrapping_add(i);
+                        matches!(dmem_offset(byte), Some(at) if at >= FREE_WORDS)
+                    });
+                    if touches {
+                        self.reach.references.insert((offset, insn.mnemonic));
+                    }
+                }
+                None => {
+                    self.reach.unresolved_accesses.insert(offset);
+                }
+            }
+        }
+
+        if let Some((reg, value)) = insn.dest {
+            if reg != 0 {
+                regs[reg as usize] = eval(value, regs);
+            }
+        }
+        Some(insn)
+    }
+
+    fn visit(&mut self, pc: u32, regs: Regs) {
+        let mut after = regs;
+        let insn = match self.step(pc, &mut after) {
+            Some(insn) => insn,
+            None => return,
+        };
+
+        let delay_slot = |scanner: &mut Scanner, mut regs: Regs| {
+            scanner.step(pc.wrapping_add(4), &mut regs);
+            regs
+        };
+
+        match insn.flow {
+            Flow::Next => self.propagate(pc.wrapping_add(4), after),
+            Flow::Stop => {}
+            Flow::Branch { target, .. } => {
+                let after = delay_slot(self, after);
+                self.propagate(target, after);
+                self.propagate(pc.wrapping_add(8), after);
+            }
+            Flow::Jump { target, .. } => {
+                let after = delay_slot(self, after);
+                self.propagate(target, after);
+            }
+            Flow::JumpRegister { reg, .. } => {
+                // the target is read before the delay slot runs
+                let target = regs[reg as usize];
+                let after = delay_slot(self, after);
+                match target {
+                    Some(target) => self.propagate(target, after),
+                    None => {
+                        self.reach.unresolved_jumps.insert(code_offset(pc).unwrap());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Follows every path from the IPL3 entry point of a big-endian `rom`.
+pub fn scan(rom: &[u8]) -> Reachability {
+    let mut scanner = Scanner {
+        rom,
+        reach: Reachability {
+            executed: BTreeSet::new(),
+            references: BTreeSet::new(),
+            unresolved_jumps: BTreeSet::new(),
+            unresolved_accesses: BTreeSet::new(),
+            exits: BTreeSet::new(),
+        },
+        states: BTreeMap::new(),
+        queue: VecDeque::new(),
+    };
+
+    let mut regs = [None; 32];
+    regs[0] = Some(0);
+    scanner.propagate(ENTRY, regs);
+    while let Some(key) = scanner.queue.pop_front() {
+        let regs = scanner.states[&key];
+        scanner.visit(key.0, regs);
+    }
+
+    scanner.reach
+}
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use std::io::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Something that happened during a run, written as one JSON object per line
+/// so wrappers don't have to scrape the human-readable log.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Target { label: &'a str, high: u32, low: u32, mask: u64 },
+    RangeStarted { y: u32, x_start: u64, x_end: u64 },
+    /// `complete` is false when the range was cut short by a hit, a
+    /// cancellation or a budget.
+    RangeFinished { y: u32, seconds: f64, complete: bool },
+    /// `targets` lists every target the checksum matches under the mask.
+    Hit { y: u32, x: u32, high: u32, low: u32, targets: Vec<&'a str> },
+    Finished { found: bool, seconds: f64 },
+}
+
+/// How far a search has got.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    /// Candidates tried so far, over every y.
+    pub tried: u64,
+    /// Every candidate before `x` in `y`, and in every earlier y, has been
+    /// tried, though later ones may have been too.
+    pub y: u32,
+    pub x: u64,
+    /// Candidates per second while hashing, so a throttle doesn't lower it.
+    pub rate: f64,
+    /// How long the rest of the range will take at the pace so far, throttle
+    /// included.
+    pub eta: Option<Duration>,
+}
+
+type ProgressCallback<'a> = Box<dyn FnMut(&Progress) + 'a>;
+
+/// Where a run's output goes: free-form log text through `Write`, optionally
+/// JSON-line events to a second stream, and optionally progress to a callback.
+pub struct Reporter<'a> {
+    log: Box<dyn Write + 'a>,
+    events: Option<Box<dyn Write + 'a>>,
+    progress: Option<ProgressCallback<'a>>,
+    /// How often to log progress, and when it last was.
+    log_progress: Option<(Duration, Instant)>,
+    last_progress: Option<Progress>,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new(log: Box<dyn Write + 'a>) -> Reporter<'a> {
+        Reporter { log, events: None, progress: None, log_progress: None, last_progress: None }
+    }
+
+    pub fn with_events(log: Box<dyn Write + 'a>, events: Box<dyn Write + 'a>) -> Reporter<'a> {
+        Reporter { log, events: Some(events), progress: None, log_progress: None, last_progress: None }
+    }
+
+    /// Also hands progress to `callback`, on the thread running the search.
+    pub fn on_progress<F: FnMut(&Progress) + 'a>(mut self, callback: F) -> Reporter<'a> {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Also logs the candidate count, hash rate and ETA every `interval`.
+    pub fn log_progress(mut self, interval: Duration) -> Reporter<'a> {
+        self.log_progress = Some((interval, Instant::now()));
+        self
+    }
+
+    /// Discards everything, for runs nobody is watching such as benchmarks.
+    pub fn sink() -> Reporter<'a> {
+        Reporter::new(Box::new(std::io::sink()))
+    }
+
+    /// The latest progress reported, which tells where a search that stopped
+    /// early can resume from.
+    pub fn last_progress(&self) -> Option<Progress> {
+        self.last_progress
+    }
+
+    pub fn progress(&mut self, progress: &Progress) -> std::io::Result<()> {
+        self.last_progress = Some(*progress);
+        if let Some(callback) = &mut self.progress {
+            callback(progress);
+        }
+
+        if let Some((interval, last)) = &mut self.log_progress {
+            if last.elapsed() >= *interval {
+                *last = Instant::now();
+                write!(self.log, "{} candidates tried, up to ({:#X}, {:#X}), {:.2} MH/s",
+                       progress.tried, progress.y, progress.x, progress.rate / 1e6)?;
+                match progress.eta {
+                    Some(eta) => writeln!(self.log, ", {}s to go", eta.as_secs())?,
+                    None => writeln!(self.log)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn event(&mut self, event: &Event) -> std::io::Result<()> {
+        if let Some(events) = &mut self.events {
+            serde_json::to_writer(&mut *events, event)?;
+            events.write_all(b"\n")?;
+            events.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Write for Reporter<'a> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.log.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.log.flush()
+    }
+}
@@ -0,0 +1,113 @@
+use byteorder::{BigEndian, ByteOrder};
+use std::fs::File;
+
+use crate::error::{Error, Result};
+
+pub use ipl3_core::{ipl3_checksum, patched_checksum, RomFormat};
+
+/// Reads all of `path`, converted to big-endian if its header says it's in
+/// another byte order. Files without a recognisable header are left alone.
+pub fn load_rom(path: &str) -> Result<(Vec<u8>, Option<RomFormat>)> {
+    let mut rom = std::fs::read(path).map_err(Error::io(path))?;
+    let format = RomFormat::detect(&rom);
+    if let Some(format) = format {
+        format.swap(&mut rom);
+    }
+    Ok((rom, format))
+}
+
+/// Reads the first 4 KiB of `path`, which hold the header and the IPL3, in
+/// big-endian whatever the byte order of the file.
+pub fn read_ipl3(path: &str) -> Result<[u8; 4096]> {
+    let file = File::open(path).map_err(Error::io(path))?;
+    let len = file.metadata().map_err(Error::io(path))?.len() as usize;
+    if len < 4096 {
+        return Err(Error::TooShort { path: path.to_string(), len, expected: 4096 });
+    }
+    ipl3_core::read_ipl3(file).map_err(Error::io(path))
+}
+
+/// Copies the first 4 KiB of a big-endian `rom` read from `path`, failing if
+/// there isn't that much.
+pub fn ipl3_of(path: &str, rom: &[u8]) -> Result<[u8; 4096]> {
+    if rom.len() < 4096 {
+        return Err(Error::TooShort { path: path.to_string(), len: rom.len(), expected: 4096 });
+    }
+    let mut ipl3 = [0u8; 4096];
+    ipl3.copy_from_slice(&rom[..4096]);
+    Ok(ipl3)
+}
+
+/// Sets the free words of a big-endian `rom`.
+pub fn set_free_words(rom: &mut [u8], y: u32, x: u32) {
+    BigEndian::write_u32(&mut rom[4088..], y);
+    BigEndian::write_u32(&mut rom[4092..], x);
+}
+
+pub const CRC_START: usize = 0x1000;
+pub const CRC_LENGTH: usize = 0x10_0000;
+
+/// The header CRC variants. IPL3 checks CRC1 and CRC2 at 0x10 and 0x14 against
+/// the first megabyte after itself, with a seed and final mix that depend on the
+/// CIC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeaderCrc {
+    Cic6102,
+    Cic6103,
+    Cic6106,
+}
+
+impl HeaderCrc {
+    fn seed(self) -> u32 {
+        match self {
+            HeaderCrc::Cic6102 => 0xF8CA_4DDC,
+            HeaderCrc::Cic6103 => 0xA388_6759,
+            HeaderCrc::Cic6106 => 0x1FEA_617A,
+        }
+    }
+}
+
+/// Computes (CRC1, CRC2) over a big-endian `rom`. A ROM shorter than the
+/// checked range is treated as padded with zeroes.
+pub fn header_crc(rom: &[u8], kind: HeaderCrc) -> (u32, u32) {
+    let seed = kind.seed();
+    let (mut t1, mut t2, mut t3, mut t4, mut t5, mut t6) = (seed, seed, seed, seed, seed, seed);
+
+    for i in (CRC_START..CRC_START + CRC_LENGTH).step_by(4) {
+        let d = rom.get(i..i + 4).map_or(0, BigEndian::read_u32);
+
+        let (sum, carry) = t6.overflowing_add(d);
+        if carry {
+            t4 = t4.wrapping_add(1);
+        }
+        t6 = sum;
+        t3 ^= d;
+        let r = d.rotate_left(d & 0x1f);
+        t5 = t5.wrapping_add(r);
+        if t2 > d {
+            t2 ^= r;
+        } else {
+            t2 ^= t6 ^ d;
+        }
+        t1 = t1.wrapping_add(t5 ^ d);
+    }
+
+    match kind {
+        HeaderCrc::Cic6102 => (t6 ^ t4 ^ t3, t5 ^ t2 ^ t1),
+        HeaderCrc::Cic6103 => ((t6 ^ t4).wrapping_add(t3), (t5 ^ t2).wrapping_add(t1)),
+        HeaderCrc::Cic6106 => (t6.wrapping_mul(t4).wrapping_add(t3), t5.wrapping_mul(t2).wrapping_add(t1)),
+    }
+}
+
+/// Reads (CRC1, CRC2) as stored in the header of a big-endian `rom`.
+pub fn stored_crc(rom: &[u8]) -> (u32, u32) {
+    (BigEndian::read_u32(&rom[0x10..]), BigEndian::read_u32(&rom[0x14..]))
+}
+
+/// Rewrites the header CRCs of a big-endian `rom` to what `kind` expects, as
+/// needed when an IPL3 for a different CIC is swapped in.
+pub fn fix_header_crc(rom: &mut [u8], kind: HeaderCrc) {
+    let (crc1, crc2) = header_crc(rom, kind);
+    BigEndian::write_u32(&mut rom[0x10..], crc1);
+    BigEndian::write_u32(&mut rom[0x14..], crc2);
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeSet;
+
+/// How many x a tile covers. It divides 2^32, so a full y is a whole number
+/// of tiles, and it's small enough that a tile takes well under a second.
+pub const TILE_X: u64 = 1 << 22;
+
+/// One y and a block of x, the unit of work handed to a worker.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    /// The tile's position in (y, x) order.
+    pub index: u64,
+    pub y: u32,
+    pub x_start: u64,
+    /// One past the last x.
+    pub x_end: u64,
+}
+
+/// The tiles of `y_start..y_end` by `0..x_end`, numbered in (y, x) order.
+#[derive(Clone, Copy, Debug)]
+pub struct Tiling {
+    pub y_start: u64,
+    pub y_end: u64,
+    pub x_end: u64,
+}
+
+impl Tiling {
+    pub fn per_y(&self) -> u64 {
+        self.x_end.div_ceil(TILE_X)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.y_end.saturating_sub(self.y_start) * self.per_y()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn tile(&self, index: u64) -> Tile {
+        let x_start = (index % self.per_y()) * TILE_X;
+        Tile {
+            index,
+            y: (self.y_start + index / self.per_y()) as u32,
+            x_start,
+            x_end: (x_start + TILE_X).min(self.x_end),
+        }
+    }
+
+    /// How many candidates the tiles before `index` cover.
+    pub fn candidates_before(&self, index: u64) -> u64 {
+        (index / self.per_y()) * self.x_end + (index % self.per_y()) * TILE_X
+    }
+
+    /// The (y, x) that tile `index` starts at, or the end of the last y for
+    /// the end of the range.
+    pub fn position(&self, index: u64) -> (u32, u64) {
+        if index < self.len() {
+            let tile = self.tile(index);
+            (tile.y, tile.x_start)
+        } else {
+            ((self.y_end - 1) as u32, self.x_end)
+        }
+    }
+}
+
+/// Tracks which tiles are done, so that although they finish out of order
+/// there's always a prefix of the range known to be fully searched, to report
+/// progress against and to resume from.
+#[derive(Debug, Default)]
+pub struct Watermark {
+    next: u64,
+    /// Tiles done past the first one that isn't.
+    ahead: BTreeSet<u64>,
+}
+
+impl Watermark {
+    pub fn finish(&mut self, index: u64) {
+        if index != self.next {
+            self.ahead.insert(index);
+            return;
+        }
+
+        self.next += 1;
+        while self.ahead.remove(&self.next) {
+            self.next += 1;
+        }
+    }
+
+    /// The first tile that isn't done; every tile before it is.
+    pub fn next(&self) -> u64 {
+        self.next
+    }
+}
@@ -0,0 +1,559 @@
+use byteorder::BigEndian;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::io::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::checksum::ChecksumInfo;
+use crate::error::{Error, Result};
+use crate::report::{Event, Progress, Reporter};
+use crate::schedule::{Tile, Tiling, Watermark};
+use crate::solver::{self, SolveResult};
+use crate::target::TargetSet;
+
+#[derive(Clone)]
+pub struct SearchOptions {
+    pub seed: u32,
+    /// The first y to search.
+    pub init: u32,
+    /// One past the last y to search.
+    pub y_end: u64,
+    /// One past the last x to search for each y.
+    pub x_end: u64,
+    pub deterministic: bool,
+    pub near_miss_mask: Option<u64>,
+    pub smt: bool,
+    pub smt_timeout: u64,
+    pub cancel: CancelToken,
+    pub budget: Budget,
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            seed: 0x3F,
+            init: 0,
+            y_end: 1 << 32,
+            x_end: 1 << 32,
+            deterministic: false,
+            near_miss_mask: None,
+            smt: false,
+            smt_timeout: 10000,
+            cancel: CancelToken::default(),
+            budget: Budget::default(),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Why a search that began at `started` and has tried `tried` candidates
+    /// should stop now, if it should. The y budget is left to the caller, as
+    /// it only applies between y values.
+    pub fn stop_reason(&self, started: Instant, tried: u64) -> Option<Outcome> {
+        if self.cancel.is_cancelled() {
+            Some(Outcome::Cancelled)
+        } else if self.budget.duration.is_some_and(|limit| started.elapsed() >= limit)
+            || self.budget.candidates.is_some_and(|limit| tried >= limit)
+        {
+            Some(Outcome::BudgetExceeded)
+        } else {
+            None
+        }
+    }
+}
+
+/// How the CPU backend shares the machine.
+#[derive(Clone, Copy, Debug)]
+pub struct CpuOptions {
+    /// How many worker threads to run, or 0 for one per core.
+    pub workers: usize,
+    /// Pin worker i to core i, wrapping round if there are more workers.
+    pub pin: bool,
+    /// Run the workers at the lowest scheduling priority. Only supported on
+    /// Unix, where on Linux it applies to the workers alone and elsewhere to
+    /// the whole process.
+    pub background: bool,
+    /// The percentage of the time workers spend hashing, from 1 to 100. After
+    /// each tile a worker sleeps long enough to keep to it.
+    pub throttle: u8,
+}
+
+impl Default for CpuOptions {
+    fn default() -> CpuOptions {
+        CpuOptions {
+            workers: 0,
+            pin: false,
+            background: false,
+            throttle: 100,
+        }
+    }
+}
+
+impl CpuOptions {
+    fn pool(&self) -> Result<ThreadPool> {
+        if !(1..=100).contains(&self.throttle) {
+            return Err(Error::Options(format!("can't throttle to {}%, it must be from 1 to 100", self.throttle)));
+        }
+        if self.background && !cfg!(unix) {
+            return Err(Error::Options("background priority is only supported on Unix".to_string()));
+        }
+        let cores = if self.pin {
+            core_affinity::get_core_ids()
+                .filter(|cores| !cores.is_empty())
+                .ok_or_else(|| Error::Options("can't list the cores to pin workers to".to_string()))?
+        } else {
+            Vec::new()
+        };
+
+        let background = self.background;
+        ThreadPoolBuilder::new()
+            .num_threads(self.workers)
+            .thread_name(|i| format!("ipl3-worker-{}", i))
+            .start_handler(move |i| {
+                if !cores.is_empty() {
+                    core_affinity::set_for_current(cores[i % cores.len()]);
+                }
+                if background {
+                    lower_priority();
+                }
+            })
+            .build()
+            .map_err(|e| Error::Backend(e.to_string()))
+    }
+}
+
+/// Drops the calling thread to the lowest priority.
+#[cfg(unix)]
+fn lower_priority() {
+    // Safety: setpriority only reads its arguments. On Linux a pid of 0 means
+    // the calling thread.
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+    }
+}
+
+#[cfg(not(unix))]
+fn lower_priority() {}
+
+/// Stops a search from another thread. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Limits on a search, each unlimited when unset. The candidate budget is
+/// exact; the others are checked between tiles, so a search can run up to a
+/// tile past them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Budget {
+    pub duration: Option<Duration>,
+    /// How many y values may be started.
+    pub y_count: Option<u64>,
+    pub candidates: Option<u64>,
+}
+
+/// How a search ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Found(Hit),
+    /// Every candidate in the range was tried.
+    Exhausted,
+    Cancelled,
+    BudgetExceeded,
+}
+
+/// The free words of a match: y goes at 0xFF8 and x at 0xFFC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub y: u32,
+    pub x: u32,
+}
+
+/// The state after every round that doesn't depend on the free words.
+pub fn midstate(seed: u32, rom: [u8; 4096]) -> ChecksumInfo<BigEndian> {
+    let mut pre_csum: ChecksumInfo<BigEndian> = ChecksumInfo::new(seed, rom);
+    pre_csum.checksum(0, 1005);
+    pre_csum
+}
+
+/// Advances a midstate by the round that mixes in y, leaving only x unknown.
+pub fn y_midstate(pre_csum: &ChecksumInfo<BigEndian>, y: u32) -> ChecksumInfo<BigEndian> {
+    let mut y_csum = pre_csum.clone();
+    y_csum.set_rom_word(1022, y);
+    y_csum.checksum(1005, 1006);
+    y_csum
+}
+
+/// Summarises how many near misses a range produced against how many a
+/// uniformly distributed checksum would give, as a sanity check on a backend.
+pub fn near_miss_summary(near_misses: u64, tried: u64, near_targets: &TargetSet) -> String {
+    let p = near_targets.keys().len() as f64 * 0.5f64.powi(near_targets.mask().count_ones() as i32);
+    let p = p.min(1.0);
+    let expected = tried as f64 * p;
+    let sd = (tried as f64 * p * (1.0 - p)).sqrt();
+    let z = if sd > 0.0 { (near_misses as f64 - expected) / sd } else { 0.0 };
+
+    format!("{} near misses in {} candidates (expected {:.1} ± {:.1}, z = {:+.2})",
+            near_misses, tried, expected, sd, z)
+}
+
+/// The checksum with x set, from a midstate that already has y.
+fn try_x(y_csum: &ChecksumInfo<BigEndian>, x: u32) -> (u32, u32) {
+    let mut csum = y_csum.clone();
+    csum.set_rom_word(1023, x);
+    csum.checksum(1006, 1008);
+    csum.finalize_checksum();
+    (csum.high, csum.low)
+}
+
+/// What a worker tells the thread running the search.
+enum Report {
+    /// The worker took the first tile of a y.
+    Started(u32),
+    Done {
+        tile: Tile,
+        /// Fewer than the tile holds if it ended in a hit or was abandoned.
+        tried: u64,
+        hit: Option<u32>,
+        near_misses: Vec<(u32, u32, u32)>,
+        /// Time spent hashing, which leaves out any throttling.
+        busy: Duration,
+    },
+}
+
+/// What the reporting thread knows of a y that's under way.
+struct YProgress {
+    start: Instant,
+    tried: u64,
+    near_misses: Vec<(u32, u32, u32)>,
+}
+
+/// How many x a worker tries between checks for a cancellation or a hit
+/// elsewhere.
+const CHECK_EVERY: u64 = 1 << 16;
+
+/// Searches one tile on the calling thread. It's abandoned part way once
+/// `abandon` says so.
+fn sweep_tile(
+    pre_csum: &ChecksumInfo<BigEndian>,
+    targets: &TargetSet,
+    near_targets: Option<&TargetSet>,
+    tile: Tile,
+    abandon: impl Fn() -> bool,
+) -> Report {
+    let start = Instant::now();
+    let y_csum = y_midstate(pre_csum, tile.y);
+    let mut near_misses = Vec::new();
+
+    let mut x = tile.x_start;
+    while x < tile.x_end {
+        let check_end = (x + CHECK_EVERY).min(tile.x_end);
+        for candidate in x..check_end {
+            let (high, low) = try_x(&y_csum, candidate as u32);
+            if let Some(near_targets) = near_targets {
+                if near_targets.contains(high, low) {
+                    near_misses.push((candidate as u32, high, low));
+                }
+            }
+
+            if targets.contains(high, low) {
+                let tried = candidate + 1 - tile.x_start;
+                return Report::Done { tile, tried, hit: Some(candidate as u32), near_misses, busy: start.elapsed() };
+            }
+        }
+        x = check_end;
+
+        if x < tile.x_end && abandon() {
+            break;
+        }
+    }
+
+    Report::Done { tile, tried: x - tile.x_start, hit: None, near_misses, busy: start.elapsed() }
+}
+
+/// Brute forces every y in `tiling` on `pool`. Workers take tiles in order,
+/// each computing its own y midstate, so a new y starts while the last one's
+/// final tiles are still running. Progress, events and near misses are
+/// reported from the calling thread as tiles come back.
+///
+/// `tried` is the count of candidates tried before this sweep, which the
+/// candidate budget applies to, and is advanced by the ones it tries.
+#[allow(clippy::too_many_arguments)]
+fn sweep(
+    pre_csum: &ChecksumInfo<BigEndian>,
+    targets: &TargetSet,
+    near_targets: Option<&TargetSet>,
+    opts: &SearchOptions,
+    cpu: &CpuOptions,
+    pool: &ThreadPool,
+    tiling: Tiling,
+    search_start: Instant,
+    tried: &mut u64,
+    out: &mut Reporter,
+) -> Result<Outcome> {
+    let base = *tried;
+    let sweep_start = Instant::now();
+    let next_tile = AtomicU64::new(0);
+    // the first tile with a hit: later tiles are skipped, and abandoned if
+    // they're under way. Without --deterministic any hit will do, so a hit
+    // stops every tile.
+    let hit_tile = AtomicU64::new(u64::MAX);
+    let stopped = Mutex::new(None);
+
+    let work = |reports: mpsc::Sender<Report>| loop {
+        let index = next_tile.fetch_add(1, Ordering::Relaxed);
+        if index >= tiling.len() || index > hit_tile.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let mut tile = tiling.tile(index);
+        let before = base + tiling.candidates_before(index);
+        let reason = opts.stop_reason(search_start, before).or_else(|| {
+            let over = opts.budget.y_count.is_some_and(|limit| tile.y as u64 - opts.init as u64 >= limit);
+            over.then_some(Outcome::BudgetExceeded)
+        });
+        if let Some(reason) = reason {
+            stopped.lock().unwrap().get_or_insert(reason);
+            break;
+        }
+        // the candidate budget can end part way through a tile
+        if let Some(limit) = opts.budget.candidates {
+            if tile.x_end - tile.x_start > limit - before {
+                tile.x_end = tile.x_start + (limit - before);
+                stopped.lock().unwrap().get_or_insert(Outcome::BudgetExceeded);
+            }
+        }
+
+        if tile.x_start == 0 && reports.send(Report::Started(tile.y)).is_err() {
+            break;
+        }
+        let report = sweep_tile(pre_csum, targets, near_targets, tile, || {
+            opts.cancel.is_cancelled() || index > hit_tile.load(Ordering::Relaxed)
+        });
+        let busy = match report {
+            Report::Done { hit: Some(_), busy, .. } => {
+                hit_tile.fetch_min(if opts.deterministic { index } else { 0 }, Ordering::Relaxed);
+                busy
+            }
+            Report::Done { busy, .. } => busy,
+            Report::Started(_) => Duration::ZERO,
+        };
+        if reports.send(report).is_err() {
+            break;
+        }
+
+        // sleeping off the throttle here, outside the tile, keeps it out of
+        // the hash rate; a stop cuts it short
+        let pause = busy.mul_f64((100 - cpu.throttle) as f64 / cpu.throttle as f64);
+        let pause_end = Instant::now() + pause;
+        while Instant::now() < pause_end && !opts.cancel.is_cancelled() && hit_tile.load(Ordering::Relaxed) == u64::MAX {
+            std::thread::sleep((pause_end - Instant::now()).min(Duration::from_millis(100)));
+        }
+    };
+
+    let mut ys: BTreeMap<u32, YProgress> = BTreeMap::new();
+    let mut watermark = Watermark::default();
+    let mut hits = Vec::new();
+    let mut busy_total = Duration::ZERO;
+    let workers = pool.current_num_threads() as f64;
+    let range_candidates = (tiling.y_end - tiling.y_start) as f64 * tiling.x_end as f64;
+    let mut handle = |report: Report| -> Result<()> {
+        let (y, done) = match report {
+            Report::Started(y) => (y, None),
+            Report::Done { tile, tried, hit, near_misses, busy } => {
+                busy_total += busy;
+                (tile.y, Some((tile, tried, hit, near_misses)))
+            }
+        };
+        let progress = match ys.entry(y) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                out.write_fmt(format_args!("executing y == {}\n", y))?;
+                out.event(&Event::RangeStarted { y, x_start: 0, x_end: tiling.x_end })?;
+                entry.insert(YProgress { start: Instant::now(), tried: 0, near_misses: Vec::new() })
+            }
+        };
+        let (tile, tile_tried, hit, near_misses) = match done {
+            Some(done) => done,
+            None => return Ok(()),
+        };
+
+        progress.tried += tile_tried;
+        progress.near_misses.extend(near_misses);
+        *tried += tile_tried;
+        if let Some(x) = hit {
+            hits.push(Hit { y, x });
+        } else if tile_tried == tile.x_end - tile.x_start && tile.x_end == tiling.tile(tile.index).x_end {
+            watermark.finish(tile.index);
+        }
+        let (at_y, at_x) = tiling.position(watermark.next());
+        let swept = (*tried - base) as f64;
+        // busy time is summed over workers that hash side by side
+        let rate = if busy_total > Duration::ZERO { swept / busy_total.as_secs_f64() * workers } else { 0.0 };
+        let pace = swept / sweep_start.elapsed().as_secs_f64();
+        let eta = Duration::try_from_secs_f64((range_candidates - swept) / pace).ok();
+        out.progress(&Progress { tried: *tried, y: at_y, x: at_x, rate, eta })?;
+
+        if ys[&y].tried == tiling.x_end {
+            let progress = ys.remove(&y).unwrap();
+            finish_y(out, y, progress, true, near_targets, tiling.x_end)?;
+        }
+        Ok(())
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let reported = pool.in_place_scope(|scope| {
+        for _ in 0..pool.current_num_threads() {
+            let sender = sender.clone();
+            let work = &work;
+            scope.spawn(move |_| work(sender));
+        }
+        drop(sender);
+
+        for message in receiver {
+            if let Err(e) = handle(message) {
+                // stops the workers; their sends fail once this returns
+                hit_tile.store(0, Ordering::Relaxed);
+                return Err(e);
+            }
+        }
+        Ok(())
+    });
+    reported?;
+
+    // whatever is left was cut short
+    for (y, progress) in std::mem::take(&mut ys) {
+        finish_y(out, y, progress, false, near_targets, tiling.x_end)?;
+    }
+
+    if let Some(hit) = hits.into_iter().min_by_key(|hit| (hit.y, hit.x)) {
+        return Ok(Outcome::Found(hit));
+    }
+    if let Some(reason) = stopped.into_inner().unwrap() {
+        return Ok(reason);
+    }
+    // a tile abandoned without a hit anywhere was cancelled
+    if watermark.next() < tiling.len() {
+        return Ok(Outcome::Cancelled);
+    }
+    Ok(Outcome::Exhausted)
+}
+
+/// Logs what a y turned up once nothing more will be searched in it.
+fn finish_y(
+    out: &mut Reporter,
+    y: u32,
+    mut progress: YProgress,
+    complete: bool,
+    near_targets: Option<&TargetSet>,
+    x_end: u64,
+) -> Result<()> {
+    if let Some(near_targets) = near_targets {
+        progress.near_misses.sort();
+        for (x, high, low) in progress.near_misses.iter() {
+            out.write_fmt(format_args!("Near miss at {:#X}, {:#X}: {:#06X} {:08X}\n", y, x, high, low))?;
+        }
+
+        // a y that ended in a hit or was stopped early wasn't fully swept,
+        // so there's no meaningful candidate count
+        if complete {
+            out.write_fmt(format_args!("Y=={}: {}\n", y,
+                                       near_miss_summary(progress.near_misses.len() as u64, x_end, near_targets)))?;
+        }
+    }
+
+    let duration = progress.start.elapsed();
+    out.event(&Event::RangeFinished { y, seconds: duration.as_secs_f64(), complete })?;
+    if complete {
+        out.write_fmt(format_args!("Inner loop took {:?}\n", duration))?;
+    }
+    Ok(())
+}
+
+/// Searches `opts.init..opts.y_end` by `0..opts.x_end` on the CPU until a hit,
+/// the end of the range, a cancellation or a budget runs out.
+pub fn cpu_search(
+    source_rom: [u8; 4096],
+    targets: &TargetSet,
+    opts: &SearchOptions,
+    cpu: &CpuOptions,
+    out: &mut Reporter,
+) -> Result<Outcome> {
+    let pre_csum = midstate(opts.seed, source_rom);
+    let near_targets = opts.near_miss_mask.map(|mask| TargetSet::with_mask(targets.targets().to_vec(), mask));
+
+    if opts.smt {
+        if !solver::AVAILABLE {
+            return Err(Error::Options("--smt needs ipl3hasher to be built with the smt feature".to_string()));
+        }
+
+        if !solver::self_check(&y_midstate(&pre_csum, opts.init), 0x1234_5678) {
+            return Err(Error::Backend("the solver's encoding of the final rounds disagrees with the checksum".to_string()));
+        }
+    }
+
+    if opts.x_end == 0 {
+        return Ok(Outcome::Exhausted);
+    }
+
+    let pool = cpu.pool()?;
+    let search_start = Instant::now();
+    let mut tried = 0u64;
+    let tiling = Tiling { y_start: opts.init as u64, y_end: opts.y_end, x_end: opts.x_end };
+    if !opts.smt {
+        return sweep(&pre_csum, targets, near_targets.as_ref(), opts, cpu, &pool, tiling, search_start, &mut tried, out);
+    }
+
+    // the solver settles a whole y at a time, so with it each y is swept
+    // separately
+    for y in tiling.y_start..tiling.y_end {
+        if let Some(outcome) = opts.stop_reason(search_start, tried) {
+            return Ok(outcome);
+        }
+        if opts.budget.y_count.is_some_and(|limit| y - tiling.y_start >= limit) {
+            return Ok(Outcome::BudgetExceeded);
+        }
+
+        let y = y as u32;
+        let y_csum = y_midstate(&pre_csum, y);
+        match solver::solve_for_x(&y_csum, targets, opts.smt_timeout) {
+            SolveResult::Found(x) => {
+                out.write_fmt(format_args!("Solver found X for y == {}\n", y))?;
+                // in deterministic mode a solver hit still has to be
+                // checked against every smaller x
+                let x = if opts.deterministic {
+                    pool.install(|| (0..x).into_par_iter().find_first(|&x| {
+                        let (high, low) = try_x(&y_csum, x);
+                        targets.contains(high, low)
+                    })).unwrap_or(x)
+                } else {
+                    x
+                };
+                return Ok(Outcome::Found(Hit { y, x }));
+            }
+            SolveResult::NoSolution => {
+                out.write_fmt(format_args!("Solver proved y == {} has no solution\n", y))?;
+            }
+            SolveResult::Unknown => {
+                let tiling = Tiling { y_start: y as u64, y_end: y as u64 + 1, ..tiling };
+                match sweep(&pre_csum, targets, near_targets.as_ref(), opts, cpu, &pool, tiling, search_start, &mut tried, out)? {
+                    Outcome::Exhausted => {}
+                    outcome => return Ok(outcome),
+                }
+            }
+        }
+    }
+
+    Ok(Outcome::Exhausted)
+}
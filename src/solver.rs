@@ -0,0 +1,213 @@
+use byteorder::BigEndian;
+
+use crate::checksum::ChecksumInfo;
+use crate::target::TargetSet;
+
+// Experimental: encodes rounds 1007 and 1008 plus finalization as bit-vector
+// constraints over x for a fixed y, and asks z3 for an x hitting a target.
+// Anything the solver can't decide within the timeout goes back to brute force.
+
+pub enum SolveResult {
+    Found(u32),
+    /// The solver proved no x works for this y, so the whole y can be skipped.
+    NoSolution,
+    Unknown,
+}
+
+pub const AVAILABLE: bool = cfg!(feature = "smt");
+
+/// Looks for an x completing `y_csum`, which must hold the midstate after
+/// round 1006 with y already in place.
+#[cfg(feature = "smt")]
+pub fn solve_for_x(y_csum: &ChecksumInfo<BigEndian>, targets: &TargetSet, timeout_ms: u64) -> SolveResult {
+    encode::solve_for_x(y_csum, targets, timeout_ms)
+}
+
+#[cfg(not(feature = "smt"))]
+pub fn solve_for_x(_y_csum: &ChecksumInfo<BigEndian>, _targets: &TargetSet, _timeout_ms: u64) -> SolveResult {
+    SolveResult::Unknown
+}
+
+/// Checks the encoding against the reference implementation for one x, so a
+/// mistake in it can't silently prune a y that has a solution.
+#[cfg(feature = "smt")]
+pub fn self_check(y_csum: &ChecksumInfo<BigEndian>, x: u32) -> bool {
+    let mut csum = y_csum.clone();
+    csum.set_rom_word(1023, x);
+    csum.checksum(1006, 1008);
+    csum.finalize_checksum();
+
+    encode::evaluate(y_csum, x) == Some((csum.high, csum.low))
+}
+
+#[cfg(not(feature = "smt"))]
+pub fn self_check(_y_csum: &ChecksumInfo<BigEndian>, _x: u32) -> bool {
+    false
+}
+
+#[cfg(feature = "smt")]
+mod encode {
+    use byteorder::BigEndian;
+    use z3::ast::{Ast, Bool, BV};
+    use z3::{Config, Context, SatResult, Solver};
+
+    use crate::checksum::{ChecksumInfo, MAGIC_NUMBER};
+    use crate::target::TargetSet;
+    use super::SolveResult;
+
+    struct Sym<'ctx> {
+        ctx: &'ctx Context,
+    }
+
+    impl<'ctx> Sym<'ctx> {
+        fn word(&self, v: u32) -> BV<'ctx> {
+            BV::from_u64(self.ctx, v as u64, 32)
+        }
+
+        // mirrors checksum_function
+        fn csum(&self, a0: &BV<'ctx>, a1: &BV<'ctx>, a2: u32) -> BV<'ctx> {
+            let a1 = a1._eq(&self.word(0)).ite(&self.word(a2), a1);
+            let prod = a0.zero_ext(32).bvmul(&a1.zero_ext(32));
+            let hi = prod.extract(63, 32);
+            let lo = prod.extract(31, 0);
+            let diff = hi.bvsub(&lo);
+            diff._eq(&self.word(0)).ite(a0, &diff)
+        }
+
+        // the reference code builds these from shift pairs, which is exactly a
+        // rotate, including the shift == 0 case
+        fn rotr(&self, v: &BV<'ctx>, amount: &BV<'ctx>) -> BV<'ctx> {
+            v.bvrotr(amount)
+        }
+
+        fn rotl(&self, v: &BV<'ctx>, amount: &BV<'ctx>) -> BV<'ctx> {
+            v.bvrotl(amount)
+        }
+
+        fn low5(&self, v: &BV<'ctx>) -> BV<'ctx> {
+            v.bvand(&self.word(0x1f))
+        }
+
+        fn top5(&self, v: &BV<'ctx>) -> BV<'ctx> {
+            v.bvlshr(&self.word(27))
+        }
+
+        // mirrors one iteration of ChecksumInfo::checksum
+        fn round(&self, b: &mut Vec<BV<'ctx>>, data_last: &BV<'ctx>, data: &BV<'ctx>, data_next: &BV<'ctx>, loop_idx: u32) {
+            let li = self.word(loop_idx);
+
+            b[0] = b[0].bvadd(&self.csum(&self.word(1007u32.wrapping_sub(loop_idx)), data, loop_idx));
+            b[1] = self.csum(&b[1], data, loop_idx);
+            b[2] = b[2].bvxor(data);
+            b[3] = b[3].bvadd(&self.csum(&data.bvadd(&self.word(5)), &self.word(MAGIC_NUMBER), loop_idx));
+
+            b[9] = data_last.bvult(data).ite(&self.csum(&b[9], data, loop_idx), &b[9].bvadd(data));
+
+            let tmp = self.rotr(data, &self.low5(data_last));
+            b[4] = b[4].bvadd(&tmp);
+            b[7] = self.csum(&b[7], &self.rotl(data, &self.low5(data_last)), loop_idx);
+
+            b[6] = data.bvult(&b[6]).ite(
+                &b[3].bvadd(&b[6]).bvxor(&data.bvadd(&li)),
+                &b[4].bvadd(data).bvxor(&b[6]),
+            );
+
+            let tmp2 = self.rotl(data, &self.top5(data_last));
+            b[5] = b[5].bvadd(&tmp2);
+            b[8] = self.csum(&b[8], &self.rotr(data, &self.top5(data_last)), loop_idx);
+
+            if loop_idx == 1008 {
+                return;
+            }
+
+            let sum = self.csum(&b[15], &tmp2, loop_idx);
+            b[15] = self.csum(&sum, &self.rotl(data_next, &self.top5(data)), loop_idx);
+
+            let sum = self.csum(&b[14], &tmp, loop_idx);
+            b[14] = self.csum(&sum, &self.rotr(data_next, &self.low5(data)), loop_idx);
+
+            let tmp3 = self.rotr(data, &self.low5(data));
+            b[13] = b[13].bvadd(&tmp3.bvadd(&self.rotr(data_next, &self.low5(data_next))));
+
+            b[10] = self.csum(&b[10].bvadd(data), data_next, loop_idx);
+            b[11] = self.csum(&b[11].bvxor(data), data_next, loop_idx);
+            b[12] = b[12].bvadd(&b[8].bvxor(data));
+        }
+
+        // mirrors ChecksumInfo::finalize_checksum, returning (high, low)
+        fn finalize(&self, b: &[BV<'ctx>]) -> (BV<'ctx>, BV<'ctx>) {
+            let mut buf = vec![b[0].clone(), b[0].clone(), b[0].clone(), b[0].clone()];
+            let one = self.word(1);
+
+            for (i, data) in b.iter().enumerate() {
+                let tmp = buf[0].bvadd(&self.rotr(data, &self.low5(data)));
+                buf[0] = tmp.clone();
+
+                buf[1] = data.bvult(&tmp).ite(&buf[1].bvadd(data), &self.csum(&buf[1], data, i as u32));
+
+                let bit1 = data.bvlshr(&one).bvand(&one);
+                let bit0 = data.bvand(&one);
+                buf[2] = bit1._eq(&bit0).ite(&buf[2].bvadd(data), &self.csum(&buf[2], data, i as u32));
+                buf[3] = bit0._eq(&one).ite(&buf[3].bvxor(data), &self.csum(&buf[3], data, i as u32));
+            }
+
+            let high = self.csum(&buf[0], &buf[1], 16).bvand(&self.word(0xffff));
+            let low = buf[3].bvxor(&buf[2]);
+            (high, low)
+        }
+    }
+
+    fn checksum_of<'ctx>(sym: &Sym<'ctx>, y_csum: &ChecksumInfo<BigEndian>, x: &BV<'ctx>) -> (BV<'ctx>, BV<'ctx>) {
+        let mut state: Vec<BV> = y_csum.buffer.iter().map(|&v| sym.word(v)).collect();
+        let data_prev = sym.word(y_csum.rom_word(1021));
+        let y = sym.word(y_csum.rom_word(1022));
+
+        sym.round(&mut state, &data_prev, &y, x, 1007);
+        sym.round(&mut state, &y, x, &sym.word(0), 1008);
+        sym.finalize(&state)
+    }
+
+    pub fn solve_for_x(y_csum: &ChecksumInfo<BigEndian>, targets: &TargetSet, timeout_ms: u64) -> SolveResult {
+        let mut cfg = Config::new();
+        cfg.set_timeout_msec(timeout_ms);
+        let ctx = Context::new(&cfg);
+        let sym = Sym { ctx: &ctx };
+
+        let x = BV::new_const(&ctx, "x", 32);
+        let (high, low) = checksum_of(&sym, y_csum, &x);
+
+        let mask_hi = sym.word((targets.mask() >> 32) as u32);
+        let mask_lo = sym.word(targets.mask() as u32);
+        let hits: Vec<Bool> = targets.keys().iter().map(|&key| {
+            Bool::and(&ctx, &[
+                &high.bvand(&mask_hi)._eq(&sym.word((key >> 32) as u32)),
+                &low.bvand(&mask_lo)._eq(&sym.word(key as u32)),
+            ])
+        }).collect();
+        let hit_refs: Vec<&Bool> = hits.iter().collect();
+
+        let solver = Solver::new(&ctx);
+        solver.assert(&Bool::or(&ctx, &hit_refs));
+
+        match solver.check() {
+            SatResult::Sat => solver.get_model()
+                .and_then(|model| model.eval(&x, true))
+                .and_then(|x| x.as_u64())
+                .map(|x| SolveResult::Found(x as u32))
+                .unwrap_or(SolveResult::Unknown),
+            SatResult::Unsat => SolveResult::NoSolution,
+            SatResult::Unknown => SolveResult::Unknown,
+        }
+    }
+
+    pub fn evaluate(y_csum: &ChecksumInfo<BigEndian>, x: u32) -> Option<(u32, u32)> {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let sym = Sym { ctx: &ctx };
+
+        let (high, low) = checksum_of(&sym, y_csum, &sym.word(x));
+        let high = high.simplify().as_u64()?;
+        let low = low.simplify().as_u64()?;
+        Some((high as u32, low as u32))
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub label: String,
+    pub high: u32,
+    pub low: u32,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#06X} {:08X} ({})", self.high, self.low, self.label)
+    }
+}
+
+pub const FULL_MASK: u64 = 0xffff_ffff_ffff;
+
+fn checksum_key(high: u32, low: u32) -> u64 {
+    ((high as u64) << 32) | (low as u64)
+}
+
+/// A small sorted table of target checksums, so that the hot loop only pays for
+/// a binary search no matter how many targets are given. Only the bits set in
+/// `mask` take part in the comparison, which makes reduced-difficulty searches
+/// possible.
+#[derive(Clone, Debug)]
+pub struct TargetSet {
+    keys: Vec<u64>,
+    targets: Vec<Target>,
+    mask: u64,
+}
+
+impl TargetSet {
+    pub fn new(targets: Vec<Target>) -> TargetSet {
+        TargetSet::with_mask(targets, FULL_MASK)
+    }
+
+    pub fn with_mask(mut targets: Vec<Target>, mask: u64) -> TargetSet {
+        // targets sharing a masked checksum are all kept, but only one key
+        // goes in the table
+        targets.sort_by_key(|t| checksum_key(t.high, t.low) & mask);
+        let mut keys: Vec<u64> = targets.iter().map(|t| checksum_key(t.high, t.low) & mask).collect();
+        keys.dedup();
+
+        TargetSet { keys, targets, mask }
+    }
+
+    /// Targets for bare 48-bit checksums, each labelled with its own value.
+    pub fn from_checksums(checksums: &[u64], mask: u64) -> TargetSet {
+        TargetSet::with_mask(checksums.iter().map(|&checksum| Target {
+            label: format!("{:012X}", checksum),
+            high: (checksum >> 32) as u32,
+            low: checksum as u32,
+        }).collect(), mask)
+    }
+
+    pub fn contains(&self, high: u32, low: u32) -> bool {
+        self.keys.binary_search(&(checksum_key(high, low) & self.mask)).is_ok()
+    }
+
+    /// Every target whose masked checksum matches, empty if none does.
+    pub fn lookup(&self, high: u32, low: u32) -> &[Target] {
+        let key = checksum_key(high, low) & self.mask;
+        let masked = |t: &Target| checksum_key(t.high, t.low) & self.mask;
+        let start = self.targets.partition_point(|t| masked(t) < key);
+        let end = self.targets.partition_point(|t| masked(t) <= key);
+        &self.targets[start..end]
+    }
+
+    /// The targets in ascending order of their masked checksum.
+    pub fn targets(&self) -> &[Target] {
+        &self.targets
+    }
+
+    /// The masked checksums in ascending order, as used for the GPU lookup table.
+    pub fn keys(&self) -> &[u64] {
+        &self.keys
+    }
+
+    pub fn mask(&self) -> u64 {
+        self.mask
+    }
+}
@@ -0,0 +1,47 @@
+use byteorder::BigEndian;
+
+use crate::checksum::{ChecksumInfo, Round};
+
+/// The state before the first round, as a round 0 with no inputs.
+fn initial(csum: &ChecksumInfo<BigEndian>) -> Round {
+    Round {
+        loop_idx: 0,
+        data_last: 0,
+        data: 0,
+        data_next: 0,
+        buffer: csum.buffer,
+    }
+}
+
+/// Every state the checksum of `rom` passes through, starting with round 0.
+pub fn rounds(seed: u32, rom: [u8; 4096]) -> Vec<Round> {
+    let mut csum: ChecksumInfo<BigEndian> = ChecksumInfo::new(seed, rom);
+    let mut rounds = vec![initial(&csum)];
+    csum.checksum_observed(0, 1008, |round| rounds.push(*round));
+    rounds
+}
+
+pub struct Divergence {
+    /// The first round after which the states differ, 0 for the initial state.
+    pub round: u32,
+    /// The state words that differ after that round.
+    pub words: Vec<usize>,
+    /// The first checksummed ROM word that differs, as an absolute word index.
+    pub first_input: Option<usize>,
+}
+
+/// Finds where the checksum states of two images first part ways, if they do.
+pub fn diff(seed: u32, a: [u8; 4096], b: [u8; 4096]) -> Option<Divergence> {
+    let a_rounds = rounds(seed, a);
+    let b_rounds = rounds(seed, b);
+
+    let (a_round, b_round) = a_rounds.iter().zip(b_rounds.iter()).find(|(a, b)| a.buffer != b.buffer)?;
+    let words = (0..16).filter(|&i| a_round.buffer[i] != b_round.buffer[i]).collect();
+    let first_input = (16..1024).find(|&i| a[i * 4..i * 4 + 4] != b[i * 4..i * 4 + 4]);
+
+    Some(Divergence {
+        round: a_round.loop_idx,
+        words,
+        first_input,
+    })
+}
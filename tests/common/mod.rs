@@ -0,0 +1,33 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use ipl3::search::{midstate, y_midstate};
+use ipl3::target::TargetSet;
+
+/// An image like those of ipl3-core/tests/vectors.txt.
+pub fn image(number: u32) -> [u8; 4096] {
+    let mut rom = [0u8; 4096];
+    let mut state = number;
+    for byte in rom.iter_mut() {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        *byte = (state >> 16) as u8;
+    }
+    rom
+}
+
+pub fn joined((high, low): (u32, u32)) -> u64 {
+    ((high as u64) << 32) | (low as u64)
+}
+
+/// The checksum of `rom` with seed 0x3F and the free words set to y, x.
+pub fn checksum(rom: [u8; 4096], y: u32, x: u32) -> u64 {
+    let mut csum = y_midstate(&midstate(0x3F, rom), y);
+    csum.set_rom_word(1023, x);
+    csum.checksum(1006, 1008);
+    csum.finalize_checksum();
+    joined((csum.high, csum.low))
+}
+
+pub fn targets(checksum: u64, mask: u64) -> TargetSet {
+    TargetSet::from_checksums(&[checksum], mask)
+}
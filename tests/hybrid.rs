@@ -0,0 +1,86 @@
+mod common;
+
+use ipl3::error::{Error, Result};
+use ipl3::hybrid::{hybrid_search, Backend, CpuBackend, Ledger};
+use ipl3::report::Reporter;
+use ipl3::search::{CpuOptions, Hit, Outcome, SearchOptions};
+use ipl3::target::TargetSet;
+
+use common::{checksum, image, targets};
+
+fn two_cpus() -> Vec<Box<dyn Backend>> {
+    vec![
+        Box::new(CpuBackend { cpu: CpuOptions::default() }),
+        Box::new(CpuBackend { cpu: CpuOptions::default() }),
+    ]
+}
+
+struct Failing;
+
+impl Backend for Failing {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    fn search(&mut self, _: [u8; 4096], _: &TargetSet, _: &SearchOptions, _: &mut Reporter) -> Result<Outcome> {
+        Err(Error::Backend("out of order".to_string()))
+    }
+}
+
+#[test]
+fn deterministic_hit_is_the_first() {
+    let rom = image(1);
+    let mask = 0xFFFF;
+    let target = checksum(rom, 40, 100);
+    let first = (0..64u32)
+        .flat_map(|y| (0..1u32 << 12).map(move |x| (y, x)))
+        .find(|&(y, x)| checksum(rom, y, x) & mask == target & mask)
+        .unwrap();
+
+    let opts = SearchOptions { y_end: 64, x_end: 1 << 12, deterministic: true, ..SearchOptions::default() };
+    let mut ledger = Ledger::new(0, 64, 1 << 12);
+    let outcome = hybrid_search(rom, &targets(target, mask), &opts, &mut two_cpus(), &mut ledger, &mut Reporter::sink()).unwrap();
+    assert_eq!(outcome, Outcome::Found(Hit { y: first.0, x: first.1 }));
+}
+
+#[test]
+fn both_backends_share_the_range() {
+    let rom = image(2);
+    let opts = SearchOptions { y_end: 256, x_end: 1 << 12, ..SearchOptions::default() };
+    let mut ledger = Ledger::new(0, 256, 1 << 12);
+    let outcome = hybrid_search(rom, &targets(0x123456789ABC, 0xFFFF_FFFF_FFFF), &opts, &mut two_cpus(), &mut ledger, &mut Reporter::sink()).unwrap();
+
+    assert_eq!(outcome, Outcome::Exhausted);
+    assert_eq!(ledger.covered(), std::slice::from_ref(&(0..256)));
+    assert!(ledger.untaken().is_empty());
+    assert_eq!(ledger.tried(), 256 << 12);
+    assert!(ledger.tried_by(0) > 0 && ledger.tried_by(1) > 0);
+    assert_eq!(ledger.tried_by(0) + ledger.tried_by(1), ledger.tried());
+}
+
+#[test]
+fn cancelled_before_starting() {
+    let opts = SearchOptions { y_end: 256, x_end: 1 << 12, ..SearchOptions::default() };
+    opts.cancel.cancel();
+    let mut ledger = Ledger::new(0, 256, 1 << 12);
+    let outcome = hybrid_search(image(3), &targets(0x123456789ABC, 0xFFFF_FFFF_FFFF), &opts, &mut two_cpus(), &mut ledger, &mut Reporter::sink()).unwrap();
+
+    assert_eq!(outcome, Outcome::Cancelled);
+    assert_eq!(ledger.untaken(), std::slice::from_ref(&(0..256)));
+}
+
+#[test]
+fn failed_work_goes_to_the_others() {
+    let opts = SearchOptions { y_end: 64, x_end: 1 << 12, ..SearchOptions::default() };
+    let mut backends: Vec<Box<dyn Backend>> = vec![Box::new(Failing), Box::new(CpuBackend { cpu: CpuOptions::default() })];
+    let mut ledger = Ledger::new(0, 64, 1 << 12);
+    let outcome = hybrid_search(image(4), &targets(0x123456789ABC, 0xFFFF_FFFF_FFFF), &opts, &mut backends, &mut ledger, &mut Reporter::sink()).unwrap();
+
+    assert_eq!(outcome, Outcome::Exhausted);
+    assert_eq!(ledger.covered(), std::slice::from_ref(&(0..64)));
+    assert_eq!(ledger.tried_by(1), 64 << 12);
+
+    let mut alone: Vec<Box<dyn Backend>> = vec![Box::new(Failing)];
+    let mut ledger = Ledger::new(0, 64, 1 << 12);
+    assert!(hybrid_search(image(4), &targets(0x123456789ABC, 0xFFFF_FFFF_FFFF), &opts, &mut alone, &mut ledger, &mut Reporter::sink()).is_err());
+}
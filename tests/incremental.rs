@@ -0,0 +1,41 @@
+mod common;
+
+use ipl3::incremental::IncrementalHasher;
+use ipl3_core::image_checksum;
+
+use common::image;
+
+#[test]
+fn edits_match_a_full_recompute() {
+    // header, the first word read, early, middle and late words and the free words
+    let edits = [3, 16, 17, 40, 511, 512, 900, 1007, 1021, 1022, 1023];
+
+    for &interval in &[1, 7, 16, 100, 1008] {
+        let mut rom = image(5);
+        let mut hasher = IncrementalHasher::new(0x3F, rom, interval);
+        assert_eq!(hasher.checksum(), image_checksum(0x3F, &rom));
+
+        for (n, &idx) in edits.iter().enumerate() {
+            let value = 0x9E37_79B9u32.wrapping_mul(n as u32 + 1);
+            hasher.set_word(idx, value);
+            rom[4 * idx..4 * idx + 4].copy_from_slice(&value.to_be_bytes());
+            assert_eq!(hasher.word(idx), value);
+            assert!(hasher.rom()[..] == rom[..]);
+            assert_eq!(hasher.checksum(), image_checksum(0x3F, &rom), "word {} every {} rounds", idx, interval);
+        }
+
+        // several edits between checksums, latest first
+        for &idx in edits.iter().rev() {
+            let value = hasher.word(idx) ^ 0xFFFF_0000;
+            hasher.set_word(idx, value);
+            rom[4 * idx..4 * idx + 4].copy_from_slice(&value.to_be_bytes());
+        }
+        assert_eq!(hasher.checksum(), image_checksum(0x3F, &rom), "every {} rounds", interval);
+    }
+}
+
+#[test]
+#[should_panic(expected = "past the end of the IPL3")]
+fn word_out_of_range() {
+    IncrementalHasher::new(0x3F, image(5), 16).set_word(1024, 0);
+}
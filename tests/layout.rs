@@ -0,0 +1,127 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use ipl3::layout::{lay_out, read_payload, Segment, FREE_WORDS, IPL3_END, IPL3_START};
+
+const KSEG0: u64 = 0x8400_0000;
+const KSEG1: u64 = 0xA400_0000;
+
+fn put<E: ByteOrder>(elf: &mut [u8], at: usize, size: usize, value: u64) {
+    match size {
+        2 => E::write_u16(&mut elf[at..], value as u16),
+        4 => E::write_u32(&mut elf[at..], value as u32),
+        _ => E::write_u64(&mut elf[at..], value),
+    }
+}
+
+/// An ELF file with a PT_LOAD segment for each (vaddr, data), in the byte
+/// order of `E`.
+fn elf<E: ByteOrder>(wide: bool, big: bool, segments: &[(u64, &[u8])]) -> Vec<u8> {
+    let word = if wide { 8 } else { 4 };
+    let (header_size, entry_size) = if wide { (0x40, 0x38) } else { (0x34, 0x20) };
+    let (phoff, phentsize, phnum) = if wide { (0x20, 0x36, 0x38) } else { (0x1C, 0x2A, 0x2C) };
+    let (p_offset, p_vaddr, p_filesz) = if wide { (0x08, 0x10, 0x20) } else { (0x04, 0x08, 0x10) };
+
+    let mut elf = vec![0u8; header_size + entry_size * segments.len()];
+    elf[..6].copy_from_slice(&[0x7F, b'E', b'L', b'F', if wide { 2 } else { 1 }, if big { 2 } else { 1 }]);
+    put::<E>(&mut elf, phoff, word, header_size as u64);
+    put::<E>(&mut elf, phentsize, 2, entry_size as u64);
+    put::<E>(&mut elf, phnum, 2, segments.len() as u64);
+
+    for (n, &(vaddr, data)) in segments.iter().enumerate() {
+        let header = header_size + n * entry_size;
+        let offset = elf.len();
+        put::<E>(&mut elf, header, 4, 1);
+        put::<E>(&mut elf, header + p_offset, word, offset as u64);
+        put::<E>(&mut elf, header + p_vaddr, word, if wide { vaddr | 0xFFFF_FFFF_0000_0000 } else { vaddr });
+        put::<E>(&mut elf, header + p_filesz, word, data.len() as u64);
+        elf.extend_from_slice(data);
+    }
+    elf
+}
+
+fn every_elf(segments: &[(u64, &[u8])]) -> Vec<Vec<u8>> {
+    vec![
+        elf::<BigEndian>(false, true, segments),
+        elf::<LittleEndian>(false, false, segments),
+        elf::<BigEndian>(true, true, segments),
+        elf::<LittleEndian>(true, false, segments),
+    ]
+}
+
+#[test]
+fn elf_in_either_segment_and_byte_order() {
+    let code = [0x3C, 0x08, 0xA4, 0x00, 0x25, 0x08, 0x00, 0x40];
+    let data = [0xDE, 0xAD, 0xBE, 0xEF];
+    for &base in &[KSEG0, KSEG1] {
+        for elf in every_elf(&[(base + 0x40, &code), (base + 0x800, &data)]) {
+            let segments = read_payload(&elf).unwrap();
+            assert_eq!(segments.len(), 2);
+            assert_eq!((segments[0].offset, &segments[0].data[..]), (0x40, &code[..]));
+            assert_eq!((segments[1].offset, &segments[1].data[..]), (0x800, &data[..]));
+
+            let template = vec![0xFF; 0x2000];
+            let rom = lay_out(&template, &segments).unwrap();
+            assert_eq!(&rom[0x40..0x48], &code);
+            assert_eq!(&rom[0x800..0x804], &data);
+            assert!(rom[0x48..0x800].iter().chain(&rom[0x804..IPL3_END]).all(|&b| b == 0));
+            assert!(rom[..IPL3_START].iter().chain(&rom[IPL3_END..]).all(|&b| b == 0xFF));
+        }
+    }
+}
+
+#[test]
+fn elf_segment_outside_the_ipl3() {
+    for &vaddr in &[KSEG1, KSEG1 + 0x3C, KSEG1 + 0x1000, 0x8000_0400] {
+        for elf in every_elf(&[(vaddr, &[0; 4])]) {
+            assert!(read_payload(&elf).is_err(), "{:#X}", vaddr);
+        }
+    }
+}
+
+#[test]
+fn elf_segment_over_the_free_words() {
+    for &(vaddr, len) in &[(KSEG1 + 0xFF0, 12), (KSEG1 + 0xFF8, 4), (KSEG1 + 0xFFC, 4)] {
+        for elf in every_elf(&[(vaddr, &[1; 12][..len])]) {
+            let segments = read_payload(&elf).unwrap();
+            assert!(lay_out(&[0; 0x1000], &segments).is_err(), "{:#X}", vaddr);
+        }
+    }
+}
+
+#[test]
+fn flat_binary_up_to_the_free_words() {
+    let fits = vec![0x5A; FREE_WORDS - IPL3_START];
+    let rom = lay_out(&[0; 0x40], &read_payload(&fits).unwrap()).unwrap();
+    assert_eq!(rom.len(), IPL3_END);
+    assert_eq!(&rom[IPL3_START..FREE_WORDS], &fits[..]);
+
+    let too_long = vec![0x5A; FREE_WORDS - IPL3_START + 1];
+    assert!(lay_out(&[0; 0x40], &read_payload(&too_long).unwrap()).is_err());
+    let past_the_end = vec![0x5A; IPL3_END];
+    assert!(lay_out(&[0; 0x40], &read_payload(&past_the_end).unwrap()).is_err());
+}
+
+#[test]
+fn hostile_offsets_and_sizes() {
+    let huge = Segment { offset: usize::MAX - 2, data: vec![0; 4] };
+    assert!(lay_out(&[0; 0x40], &[huge]).is_err());
+
+    for wide in [false, true] {
+        let (phoff, phentsize, p_filesz) = if wide { (0x20, 0x36, 0x20) } else { (0x1C, 0x2A, 0x10) };
+        let word = if wide { 8 } else { 4 };
+        let good = elf::<BigEndian>(wide, true, &[(KSEG1 + 0x40, &[0; 4]), (KSEG1 + 0x80, &[0; 4])]);
+        let header = BigEndian::read_uint(&good[phoff..], word) as usize;
+
+        // a table whose second entry wraps around the address space
+        let mut elf = good.clone();
+        put::<BigEndian>(&mut elf, phoff, word, u64::MAX >> (64 - 8 * word));
+        assert!(read_payload(&elf).is_err());
+        let mut elf = good.clone();
+        put::<BigEndian>(&mut elf, phentsize, 2, 0xFFFF);
+        assert!(read_payload(&elf).is_err());
+
+        // a segment running past the end of memory
+        let mut elf = good;
+        put::<BigEndian>(&mut elf, header + p_filesz, word, u64::MAX >> (64 - 8 * word));
+        assert!(read_payload(&elf).is_err());
+    }
+}
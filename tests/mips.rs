@@ -0,0 +1,126 @@
+use ipl3::mips::{decode, safety, AluOp, Flow, Mem, Safety, Value, RA};
+
+const PC: u32 = 0xA400_0100;
+
+fn r(funct: u32, rs: u32, rt: u32, rd: u32, sa: u32) -> u32 {
+    rs << 21 | rt << 16 | rd << 11 | sa << 6 | funct
+}
+
+fn i(op: u32, rs: u32, rt: u32, imm: u16) -> u32 {
+    op << 26 | rs << 21 | rt << 16 | imm as u32
+}
+
+fn cop0(rs: u32, funct: u32) -> u32 {
+    0x10 << 26 | rs << 21 | funct
+}
+
+#[test]
+fn special() {
+    let nop = decode(0, PC);
+    assert_eq!((nop.mnemonic, nop.dest), ("nop", None));
+
+    let sll = decode(r(0x00, 0, 9, 8, 4), PC);
+    assert_eq!(sll.mnemonic, "sll");
+    assert_eq!(sll.dest, Some((8, Value::RegImm(AluOp::Sll, 9, 4))));
+
+    let addu = decode(r(0x21, 4, 5, 2, 0), PC);
+    assert_eq!(addu.dest, Some((2, Value::RegReg(AluOp::Add, 4, 5))));
+    assert_eq!(addu.hazard, None);
+    assert!(decode(r(0x20, 4, 5, 2, 0), PC).hazard.is_some());
+
+    let jr = decode(r(0x08, RA as u32, 0, 0, 0), PC);
+    assert_eq!(jr.flow, Flow::JumpRegister { reg: RA, link: false });
+    let jalr = decode(r(0x09, 8, 0, 31, 0), PC);
+    assert_eq!(jalr.flow, Flow::JumpRegister { reg: 8, link: true });
+    assert_eq!(jalr.dest, Some((RA, Value::Const(PC + 8))));
+
+    let syscall = decode(r(0x0C, 0, 0, 0, 0), PC);
+    assert_eq!(syscall.flow, Flow::Stop);
+    assert!(syscall.hazard.is_some());
+    assert!(decode(r(0x34, 1, 2, 0, 0), PC).hazard.is_some());
+    assert_eq!(decode(r(0x01, 0, 0, 0, 0), PC).mnemonic, "(reserved)");
+}
+
+#[test]
+fn branches_and_jumps() {
+    // backwards by two instructions from the delay slot
+    let beq = decode(i(0x04, 1, 2, -2i16 as u16), PC);
+    assert_eq!(beq.flow, Flow::Branch { target: PC + 4 - 8, link: false });
+    assert_eq!(beq.dest, None);
+
+    let bgezal = decode(i(0x01, 3, 0x11, 0x10), PC);
+    assert_eq!(bgezal.mnemonic, "bgezal");
+    assert_eq!(bgezal.flow, Flow::Branch { target: PC + 4 + 0x40, link: true });
+    assert_eq!(bgezal.dest, Some((RA, Value::Const(PC + 8))));
+
+    let j = decode(0x02 << 26 | 0x40, PC);
+    assert_eq!(j.flow, Flow::Jump { target: 0xA000_0100, link: false });
+    let jal = decode(0x03 << 26 | 0x40, PC);
+    assert_eq!(jal.flow, Flow::Jump { target: 0xA000_0100, link: true });
+    assert_eq!(jal.dest, Some((RA, Value::Const(PC + 8))));
+}
+
+#[test]
+fn immediates() {
+    assert_eq!(decode(i(0x0F, 0, 8, 0xA400), PC).dest, Some((8, Value::Const(0xA400_0000))));
+    assert_eq!(decode(i(0x09, 8, 8, 0xFFFC), PC).dest, Some((8, Value::RegImm(AluOp::Add, 8, 0xFFFF_FFFC))));
+    assert_eq!(decode(i(0x0D, 8, 9, 0x8000), PC).dest, Some((9, Value::RegImm(AluOp::Or, 8, 0x8000))));
+    assert!(decode(i(0x08, 8, 8, 1), PC).hazard.is_some());
+    assert_eq!(AluOp::Add.apply(0xA400_0000, 0xFFFF_FFFC), 0xA3FF_FFFC);
+    assert_eq!(AluOp::Sra.apply(0x8000_0000, 4), 0xF800_0000);
+}
+
+#[test]
+fn loads_and_stores() {
+    let lw = decode(i(0x23, 8, 9, 0x0FF8), PC);
+    assert_eq!(lw.mem, Some(Mem { store: false, base: 8, offset: 0xFF8, size: 4 }));
+    assert_eq!(lw.dest, Some((9, Value::Unknown)));
+
+    let sd = decode(i(0x3F, 29, 31, -8i16 as u16), PC);
+    assert_eq!(sd.mem, Some(Mem { store: true, base: 29, offset: -8, size: 8 }));
+    assert_eq!(sd.dest, None);
+
+    let lwc1 = decode(i(0x31, 8, 2, 0), PC);
+    assert!(lwc1.mem.is_some() && lwc1.dest.is_none() && lwc1.hazard.is_some());
+    assert!(decode(i(0x2F, 8, 0, 0), PC).hazard.is_some());
+}
+
+#[test]
+fn coprocessors() {
+    assert_eq!(decode(cop0(0x00, 0) | 9 << 16, PC).dest, Some((9, Value::Unknown)));
+    assert!(decode(cop0(0x04, 0), PC).hazard.is_some());
+
+    // the CO operations decode the same whatever the rest of rs holds
+    for rs in 0x10..=0x1F {
+        let eret = decode(cop0(rs, 0x18), PC);
+        assert_eq!((eret.mnemonic, eret.flow), ("eret", Flow::Stop));
+        assert_eq!(decode(cop0(rs, 0x02), PC).mnemonic, "tlbwi");
+        assert_eq!(decode(cop0(rs, 0x06), PC).mnemonic, "tlbwr");
+        assert_eq!(decode(cop0(rs, 0x01), PC).mnemonic, "tlbr");
+        assert_eq!(decode(cop0(rs, 0x08), PC).mnemonic, "tlbp");
+        assert_eq!(decode(cop0(rs, 0x3F), PC).mnemonic, "(reserved)");
+    }
+    assert_eq!(decode(cop0(0x08, 0), PC).mnemonic, "(reserved)");
+
+    assert!(decode(0x11 << 26, PC).hazard.is_some());
+    assert!(decode(0x12 << 26, PC).hazard.is_some());
+    assert_eq!(decode(0x13 << 26, PC).flow, Flow::Stop);
+}
+
+#[test]
+fn safety_of_words() {
+    assert_eq!(safety(0, PC), Safety::NoEffect);
+    assert_eq!(safety(r(0x21, 0, 0, 0, 0), PC), Safety::NoEffect);
+    assert_eq!(safety(i(0x09, 0, 8, 1), PC), Safety::WritesRegister(8));
+    assert!(matches!(safety(i(0x23, 8, 9, 0), PC), Safety::Unsafe(_)));
+    assert!(matches!(safety(i(0x04, 0, 0, 1), PC), Safety::Unsafe(_)));
+    assert!(matches!(safety(cop0(0x10, 0x18), PC), Safety::Unsafe(_)));
+
+    // mthi, mtlo and the multiplies and divides clobber HI/LO
+    for funct in [0x11, 0x13, 0x18, 0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E, 0x1F] {
+        assert!(decode(r(funct, 4, 5, 0, 0), PC).writes_hi_lo);
+        assert!(matches!(safety(r(funct, 4, 5, 0, 0), PC), Safety::Unsafe(_)), "funct {:#X}", funct);
+    }
+    assert!(!decode(r(0x10, 0, 0, 2, 0), PC).writes_hi_lo);
+    assert_eq!(safety(r(0x10, 0, 0, 2, 0), PC), Safety::WritesRegister(2));
+}
@@ -0,0 +1,156 @@
+use ipl3::patch::{apply_bps, apply_ips, apply_patch, make_bps, make_ips};
+
+const EOF_OFFSET: usize = 0x45_4F46;
+
+fn rom(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+fn changed(original: &[u8], runs: &[(usize, usize)]) -> Vec<u8> {
+    let mut patched = original.to_vec();
+    for &(start, end) in runs {
+        for byte in &mut patched[start..end] {
+            *byte = !*byte;
+        }
+    }
+    patched
+}
+
+fn round_trip(original: &[u8], patched: &[u8]) {
+    let ips = make_ips(original, patched).unwrap();
+    assert!(apply_ips(original, &ips).unwrap() == patched, "IPS round trip");
+    assert!(apply_patch(original, &ips).unwrap() == patched, "IPS through apply_patch");
+
+    let bps = make_bps(original, patched);
+    assert!(apply_bps(original, &bps).unwrap() == patched, "BPS round trip");
+    assert!(apply_patch(original, &bps).unwrap() == patched, "BPS through apply_patch");
+}
+
+#[test]
+fn ipl3_swap() {
+    let original = rom(1 << 20);
+    round_trip(&original, &changed(&original, &[(0x40, 0x100), (0x200, 0xFF8), (0xFFC, 0x1000)]));
+}
+
+#[test]
+fn unchanged() {
+    let original = rom(4096);
+    round_trip(&original, &original);
+}
+
+#[test]
+fn long_runs_are_split() {
+    let original = rom(1 << 20);
+    let patched = changed(&original, &[(0x1000, 0x31000)]);
+    round_trip(&original, &patched);
+
+    // magic, four records of at most 0xFFFF bytes and the end marker
+    let ips = make_ips(&original, &patched).unwrap();
+    assert_eq!(ips.len(), 5 + 4 * 5 + 0x30000 + 3);
+}
+
+#[test]
+fn record_at_the_eof_offset() {
+    let original = rom(EOF_OFFSET + 0x1000);
+    round_trip(&original, &changed(&original, &[(EOF_OFFSET, EOF_OFFSET + 16)]));
+}
+
+#[test]
+fn chunk_at_the_eof_offset() {
+    // the third chunk of this run would start at the offset
+    let original = rom(EOF_OFFSET + 0x1000);
+    round_trip(&original, &changed(&original, &[(EOF_OFFSET - 2 * 0xFFFF, EOF_OFFSET + 0x100)]));
+}
+
+#[test]
+fn growing_and_truncating() {
+    let original = rom(8192);
+    let mut longer = original.clone();
+    longer.extend_from_slice(&[0xAA; 300]);
+    round_trip(&original, &longer);
+
+    let shorter = changed(&original[..5000], &[(100, 200)]);
+    round_trip(&original, &shorter);
+}
+
+#[test]
+fn truncated_ips() {
+    let original = rom(4096);
+    let ips = make_ips(&original, &changed(&original, &[(0x40, 0x80)])).unwrap();
+    assert!(apply_ips(&original, &ips[..ips.len() - 10]).is_err());
+    assert!(apply_ips(&original, b"PATCH").is_err());
+}
+
+#[test]
+fn bad_bps_crc() {
+    let original = rom(4096);
+    let mut bps = make_bps(&original, &changed(&original, &[(0x40, 0x80)]));
+
+    // a patch made for another ROM
+    assert!(apply_bps(&rom(4095), &bps).is_err());
+    let mut other = original.clone();
+    other[0] ^= 1;
+    assert!(apply_bps(&other, &bps).is_err());
+
+    // a damaged patch
+    let middle = bps.len() / 2;
+    bps[middle] ^= 1;
+    assert!(apply_bps(&original, &bps).is_err());
+}
+
+fn bps_number(mut n: u64, out: &mut Vec<u8>) {
+    loop {
+        let digit = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(0x80 | digit);
+            return;
+        }
+        out.push(digit);
+        n -= 1;
+    }
+}
+
+/// A BPS patch for `source` with the given header numbers and actions, and
+/// CRCs that pass so the numbers themselves get used.
+fn crafted_bps(source: &[u8], header: [u64; 3], actions: &[u64]) -> Vec<u8> {
+    let mut bps = b"BPS1".to_vec();
+    for &n in header.iter().chain(actions) {
+        bps_number(n, &mut bps);
+    }
+    bps.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    bps.extend_from_slice(&0u32.to_le_bytes());
+    let crc = crc32fast::hash(&bps);
+    bps.extend_from_slice(&crc.to_le_bytes());
+    bps
+}
+
+#[test]
+fn hostile_bps_sizes() {
+    let source = rom(64);
+    let len = source.len() as u64;
+    let huge = usize::MAX as u64;
+
+    // metadata running past the end of memory
+    assert!(apply_bps(&source, &crafted_bps(&source, [len, 64, huge - 2], &[])).is_err());
+    // a target far bigger than the patch could ever make
+    assert!(apply_bps(&source, &crafted_bps(&source, [len, huge, 0], &[0])).is_err());
+    // reads longer than the target, the source or the patch
+    assert!(apply_bps(&source, &crafted_bps(&source, [len, 64, 0], &[64 << 2])).is_err());
+    assert!(apply_bps(&source, &crafted_bps(&source, [len, huge, 0], &[huge - 2])).is_err());
+    assert!(apply_bps(&source, &crafted_bps(&source, [len, huge, 0], &[(1 << 40 | 1) << 2 | 1])).is_err());
+    // a source copy whose offset overflows after a good one
+    assert!(apply_bps(&source, &crafted_bps(&source, [len, 64, 0], &[2, 0, 2, (i64::MAX as u64) << 1])).is_err());
+    assert!(apply_bps(&source, &crafted_bps(&source, [len, huge, 0], &[huge - 1, 0])).is_err());
+    // a target copy from before the start
+    assert!(apply_bps(&source, &crafted_bps(&source, [len, 64, 0], &[0, 3, 3])).is_err());
+    // numbers too big for 64 bits
+    let mut overlong = crafted_bps(&source, [len, 64, 0], &[]);
+    overlong.splice(4..5, vec![0x7F; 10]);
+    assert!(apply_bps(&source, &overlong).is_err());
+}
+
+#[test]
+fn not_a_patch() {
+    assert!(apply_patch(&rom(16), b"hello").is_err());
+}
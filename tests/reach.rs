@@ -0,0 +1,72 @@
+use ipl3::reach::{scan, FREE_WORDS};
+
+const RA: u32 = 31;
+const T0: u32 = 8;
+const T1: u32 = 9;
+
+const NOP: u32 = 0;
+const BREAK: u32 = 0x0D;
+
+fn i(op: u32, rs: u32, rt: u32, imm: u16) -> u32 {
+    op << 26 | rs << 21 | rt << 16 | imm as u32
+}
+
+fn jal(offset: usize) -> u32 {
+    0x03 << 26 | ((0x0400_0000 + offset as u32) >> 2)
+}
+
+/// An image with `code` at the IPL3 entry point and the rest zero.
+fn image(code: &[u32]) -> Vec<u8> {
+    let mut rom = vec![0u8; 4096];
+    for (n, word) in code.iter().enumerate() {
+        rom[0x40 + 4 * n..0x44 + 4 * n].copy_from_slice(&word.to_be_bytes());
+    }
+    rom
+}
+
+#[test]
+fn function_returns_to_each_caller() {
+    let rom = image(&[
+        jal(0x60),                      // 0x40
+        NOP,
+        jal(0x60),                      // 0x48
+        NOP,
+        i(0x0F, 0, T0, 0xA400),         // 0x50 lui t0, 0xA400
+        i(0x23, T0, T1, 0x0FF8),        // lw t1, 0xFF8(t0)
+        BREAK,
+        NOP,
+        RA << 21 | 0x08,                // 0x60 jr ra
+        NOP,
+    ]);
+    let reach = scan(&rom);
+
+    let expected: Vec<usize> = (0x40..=0x58).step_by(4).chain(vec![0x60, 0x64]).collect();
+    assert_eq!(reach.executed.iter().copied().collect::<Vec<_>>(), expected);
+    assert!(reach.unresolved_jumps.is_empty());
+    assert!(reach.references.contains(&(0x54, "lw")));
+    assert!(reach.free_words_reachable());
+    assert!(!reach.free_words_executed());
+}
+
+#[test]
+fn loop_on_ra_converges() {
+    let rom = image(&[
+        i(0x0F, 0, RA, 0xA400),         // 0x40 lui ra, 0xA400
+        i(0x09, RA, RA, 4),             // 0x44 addiu ra, ra, 4
+        i(0x05, RA, 0, -2i16 as u16),   // bne ra, zero, 0x44
+        NOP,
+        BREAK,                          // 0x50
+    ]);
+    let reach = scan(&rom);
+
+    assert_eq!(reach.executed.iter().copied().collect::<Vec<_>>(), vec![0x40, 0x44, 0x48, 0x4C, 0x50]);
+    assert!(!reach.free_words_reachable());
+}
+
+#[test]
+fn falling_into_the_free_words() {
+    let reach = scan(&image(&[]));
+    assert!(reach.free_words_executed());
+    assert!(reach.executed.contains(&FREE_WORDS));
+    assert!(reach.exits.contains(&0xA400_1000));
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use ipl3::report::Reporter;
+use ipl3::schedule::{Tiling, Watermark, TILE_X};
+use ipl3::search::{cpu_search, midstate, y_midstate, CpuOptions, Hit, Outcome, SearchOptions};
+use ipl3::target::{TargetSet, FULL_MASK};
+
+use common::{checksum, image, joined, targets};
+
+/// Checks that the tiles of `tiling` cover every (y, x) once, in order.
+fn check_cover(tiling: Tiling) {
+    let mut next = (tiling.y_start, 0);
+    let mut candidates = 0;
+    for index in 0..tiling.len() {
+        let tile = tiling.tile(index);
+        assert_eq!(tile.index, index);
+        if next.1 == tiling.x_end {
+            next = (next.0 + 1, 0);
+        }
+        assert_eq!((tile.y as u64, tile.x_start), next, "tile {} of {:?}", index, tiling);
+        assert!(tile.x_start < tile.x_end && tile.x_end - tile.x_start <= TILE_X);
+        assert_eq!(tiling.candidates_before(index), candidates);
+        assert_eq!(tiling.position(index), (tile.y, tile.x_start));
+
+        candidates += tile.x_end - tile.x_start;
+        next.1 = tile.x_end;
+    }
+
+    if tiling.is_empty() {
+        return;
+    }
+    assert_eq!(next, (tiling.y_end - 1, tiling.x_end));
+    assert_eq!(candidates, (tiling.y_end - tiling.y_start) * tiling.x_end);
+    assert_eq!(tiling.candidates_before(tiling.len()), candidates);
+    assert_eq!(tiling.position(tiling.len()), ((tiling.y_end - 1) as u32, tiling.x_end));
+}
+
+#[test]
+fn tiles_cover_the_range_once() {
+    // whole tiles, a partial last tile in x, less than a tile, and full y
+    for &x_end in &[2 * TILE_X, 2 * TILE_X + 100, 100, 1, 1 << 32] {
+        check_cover(Tiling { y_start: 5, y_end: 8, x_end });
+        // a single y, and one at the very end of the y range
+        check_cover(Tiling { y_start: 9, y_end: 10, x_end });
+        check_cover(Tiling { y_start: (1 << 32) - 2, y_end: 1 << 32, x_end });
+    }
+
+    let tiling = Tiling { y_start: 5, y_end: 8, x_end: 2 * TILE_X + 100 };
+    assert_eq!(tiling.per_y(), 3);
+    assert_eq!(tiling.len(), 9);
+    let last = tiling.tile(8);
+    assert_eq!((last.y, last.x_start, last.x_end), (7, 2 * TILE_X, 2 * TILE_X + 100));
+
+    assert_eq!(Tiling { y_start: 0, y_end: 1, x_end: 1 << 32 }.per_y(), (1 << 32) / TILE_X);
+    assert!(Tiling { y_start: 8, y_end: 8, x_end: 100 }.is_empty());
+    assert!(Tiling { y_start: 8, y_end: 3, x_end: 100 }.is_empty());
+}
+
+#[test]
+fn watermark_follows_out_of_order_finishes() {
+    let mut watermark = Watermark::default();
+    assert_eq!(watermark.next(), 0);
+
+    for (index, next) in [(2, 0), (1, 0), (4, 0), (0, 3), (3, 5), (6, 5), (5, 7)] {
+        watermark.finish(index);
+        assert_eq!(watermark.next(), next, "after finishing tile {}", index);
+    }
+}
+
+#[test]
+fn lowest_of_two_planted_hits_wins() {
+    // the later y has the smaller x, so the hits must be ordered by y first
+    let rom = image(12);
+    let checksums = [checksum(rom, 5, 10), checksum(rom, 2, 900)];
+    let targets = TargetSet::from_checksums(&checksums, FULL_MASK);
+
+    let opts = SearchOptions { y_end: 8, x_end: 1 << 10, deterministic: true, ..SearchOptions::default() };
+    let cpu = CpuOptions { workers: 4, ..CpuOptions::default() };
+    for _ in 0..10 {
+        let outcome = cpu_search(rom, &targets, &opts, &cpu, &mut Reporter::sink()).unwrap();
+        assert_eq!(outcome, Outcome::Found(Hit { y: 2, x: 900 }));
+    }
+}
+
+#[test]
+fn deterministic_hit_with_workers_finishing_out_of_order() {
+    let rom = image(11);
+
+    // several hits in most y, so tiles after the first hit find some too
+    let mask = 0x3FF;
+    let target = 0x2A5u64;
+    let pre_csum = midstate(0x3F, rom);
+    let first = (0..64u32)
+        .flat_map(|y| (0..1u32 << 8).map(move |x| (y, x)))
+        .find(|&(y, x)| {
+            let mut csum = y_midstate(&pre_csum, y);
+            csum.set_rom_word(1023, x);
+            csum.checksum(1006, 1008);
+            csum.finalize_checksum();
+            joined((csum.high, csum.low)) & mask == target
+        })
+        .unwrap();
+
+    let targets = targets(target, mask);
+    let opts = SearchOptions { y_end: 64, x_end: 1 << 8, deterministic: true, ..SearchOptions::default() };
+    let cpu = CpuOptions { workers: 8, ..CpuOptions::default() };
+    for _ in 0..10 {
+        let outcome = cpu_search(rom, &targets, &opts, &cpu, &mut Reporter::sink()).unwrap();
+        assert_eq!(outcome, Outcome::Found(Hit { y: first.0, x: first.1 }));
+    }
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use ipl3::report::Reporter;
+use ipl3::rom::set_free_words;
+use ipl3::search::{cpu_search, midstate, y_midstate, CpuOptions, Hit, Outcome, SearchOptions};
+use ipl3_core::patched_checksum;
+
+use common::{image, joined, targets};
+
+/// Searches, patches the hit into the image and checks the patched image
+/// from scratch, returning the hit.
+fn search_and_verify(rom: [u8; 4096], target: u64, mask: u64, opts: &SearchOptions) -> Hit {
+    let outcome = cpu_search(rom, &targets(target, mask), opts, &CpuOptions::default(), &mut Reporter::sink()).unwrap();
+    let hit = match outcome {
+        Outcome::Found(hit) => hit,
+        other => panic!("expected a hit, got {:?}", other),
+    };
+
+    let mut patched = rom;
+    set_free_words(&mut patched, hit.y, hit.x);
+    assert_eq!(joined(patched_checksum(opts.seed, patched, hit.y, hit.x)) & mask, target & mask);
+    assert_eq!(joined(ipl3_core::image_checksum(opts.seed, &patched)) & mask, target & mask);
+    hit
+}
+
+#[test]
+fn masked_16_bit_finds_the_first_hit() {
+    let rom = image(7);
+    let mask = 0xFFFF;
+    // the checksum of a known candidate, so the range holds an answer
+    let target = joined(patched_checksum(0x3F, rom, 2, 0x345));
+
+    let pre_csum = midstate(0x3F, rom);
+    let first = (0..3u32)
+        .flat_map(|y| (0..1u32 << 12).map(move |x| (y, x)))
+        .find(|&(y, x)| {
+            let mut csum = y_midstate(&pre_csum, y);
+            csum.set_rom_word(1023, x);
+            csum.checksum(1006, 1008);
+            csum.finalize_checksum();
+            joined((csum.high, csum.low)) & mask == target & mask
+        })
+        .unwrap();
+
+    let opts = SearchOptions { y_end: 3, x_end: 1 << 12, deterministic: true, ..SearchOptions::default() };
+    let hit = search_and_verify(rom, target, mask, &opts);
+    assert_eq!((hit.y, hit.x), first);
+}
+
+#[test]
+fn masked_24_bit_round_trips_through_patching() {
+    let rom = image(8);
+    let mask = 0xFF_FFFF;
+    let target = joined(patched_checksum(0x3F, rom, 3, 0x1234));
+
+    let opts = SearchOptions { y_end: 4, x_end: 1 << 16, ..SearchOptions::default() };
+    let hit = search_and_verify(rom, target, mask, &opts);
+    assert!(hit.y < 4 && (hit.x as u64) < opts.x_end);
+}
+
+#[test]
+fn masked_miss_exhausts_the_range() {
+    let rom = image(9);
+    let target = joined(patched_checksum(0x3F, rom, 100, 100));
+    let opts = SearchOptions { y_end: 2, x_end: 16, ..SearchOptions::default() };
+    let outcome = cpu_search(rom, &targets(target, 0xFF_FFFF), &opts, &CpuOptions::default(), &mut Reporter::sink()).unwrap();
+    assert_eq!(outcome, Outcome::Exhausted);
+}
@@ -0,0 +1,24 @@
+use ipl3::target::{Target, TargetSet};
+
+fn target(label: &str, high: u32, low: u32) -> Target {
+    Target { label: label.to_string(), high, low }
+}
+
+#[test]
+fn targets_sharing_a_masked_checksum_are_all_kept() {
+    let targets = TargetSet::with_mask(vec![
+        target("b", 0x1234, 0xAB00_00FF),
+        target("c", 0x0000, 0x0000_0001),
+        target("a", 0x5678, 0xCD00_00FF),
+    ], 0xFFFF);
+
+    assert_eq!(targets.keys(), &[0x0001, 0x00FF]);
+    assert_eq!(targets.targets().len(), 3);
+
+    let labels: Vec<&str> = targets.lookup(0, 0x00FF).iter().map(|t| t.label.as_str()).collect();
+    assert_eq!(labels.len(), 2);
+    assert!(labels.contains(&"a") && labels.contains(&"b"));
+    assert_eq!(targets.lookup(0xFFFF, 0x1234_0001)[0].label, "c");
+    assert!(targets.lookup(0, 0x0002).is_empty());
+    assert!(targets.contains(0, 0xFFFF_00FF) && !targets.contains(0, 0x0100));
+}